@@ -0,0 +1,346 @@
+// pythonを通さずに回路を生成・変換するコマンド
+//
+// q-divide select --n 5 [--data table.csv] [--length 20] [--seed 1] [--density 1.0]
+//                       [--uncompute toffoli|measurement] [--format qasm|qasm3|pyzx]
+// q-divide stats file.qasm|file.json
+// q-divide convert in.qasm out.qasm|out.qasm3|out.json
+use prepare_circuit::error::Error;
+use prepare_circuit::primitive::Circuit;
+use prepare_circuit::pyzx::json::PyzxCircuitJson;
+use prepare_circuit::pyzx::to_json::to_pyzx_circuit;
+use prepare_circuit::qasm::from_qasm::{from_qasm, ParseError};
+use prepare_circuit::select_gates::layered::{
+    check_density, check_length, parse_uncompute, uniform_layered_internal,
+    uniform_layered_internal_with_data,
+};
+use prepare_circuit::select_gates::simple_select_controls::Uncompute;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::process;
+
+const USAGE: &str = "usage:
+  q-divide select --n <n> [--data <table.csv>] [--length <length>] [--seed <seed>]
+                  [--density <density>] [--uncompute toffoli|measurement]
+                  [--format qasm|qasm3|pyzx]
+  q-divide stats <file.qasm|file.json>
+  q-divide convert <in.qasm> <out.qasm|out.qasm3|out.json>";
+
+#[derive(Debug)]
+enum CliError {
+    // 引数が読めない
+    Usage(String),
+    Io(String, io::Error),
+    Circuit(Error),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CliError::Usage(message) => write!(f, "{}\n\n{}", message, USAGE),
+            CliError::Io(path, error) => write!(f, "{}: {}", path, error),
+            CliError::Circuit(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl From<Error> for CliError {
+    fn from(error: Error) -> CliError {
+        CliError::Circuit(error)
+    }
+}
+
+impl From<ParseError> for CliError {
+    fn from(error: ParseError) -> CliError {
+        CliError::Circuit(error.into())
+    }
+}
+
+impl From<serde_json::Error> for CliError {
+    fn from(error: serde_json::Error) -> CliError {
+        CliError::Circuit(error.into())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Qasm,
+    Qasm3,
+    Pyzx,
+}
+
+impl Format {
+    fn parse(format: &str) -> Result<Format, CliError> {
+        match format {
+            "qasm" => Ok(Format::Qasm),
+            "qasm3" => Ok(Format::Qasm3),
+            "pyzx" => Ok(Format::Pyzx),
+            _ => Err(CliError::Usage(format!("unknown format {:?}", format))),
+        }
+    }
+    // 出力先の拡張子から決める
+    fn from_path(path: &str) -> Result<Format, CliError> {
+        match path.rsplit('.').next() {
+            Some("qasm") => Ok(Format::Qasm),
+            Some("qasm3") => Ok(Format::Qasm3),
+            Some("json") => Ok(Format::Pyzx),
+            _ => Err(CliError::Usage(format!(
+                "cannot tell the format of {} from its extension",
+                path
+            ))),
+        }
+    }
+    fn write(&self, circuit: &Circuit) -> Result<String, CliError> {
+        Ok(match self {
+            Format::Qasm => circuit.to_qasm().to_string(),
+            Format::Qasm3 => circuit.to_qasm3().to_string(),
+            Format::Pyzx => serde_json::to_string(&to_pyzx_circuit(circuit)?)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct SelectOptions {
+    n: i32,
+    data: Option<String>,
+    length: Option<usize>,
+    seed: Option<u64>,
+    density: f64,
+    uncompute: Uncompute,
+    format: Format,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Command {
+    Select(SelectOptions),
+    Stats(String),
+    Convert(String, String),
+}
+
+fn parse_value<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, CliError> {
+    value
+        .parse()
+        .map_err(|_| CliError::Usage(format!("invalid value {:?} for {}", value, option)))
+}
+
+// cyclomatic complexity: 1 + 9(match) + 2(loop) = 12
+fn parse_select(args: &[String]) -> Result<SelectOptions, CliError> {
+    let mut n = None;
+    let mut options = SelectOptions {
+        n: 0,
+        data: None,
+        length: None,
+        seed: None,
+        density: 1.0,
+        uncompute: Uncompute::Toffoli,
+        format: Format::Qasm,
+    };
+    let mut args = args.iter();
+    while let Some(option) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| CliError::Usage(format!("{} needs a value", option)))?;
+        match option.as_str() {
+            "--n" => n = Some(parse_value(option, value)?),
+            "--data" => options.data = Some(value.clone()),
+            "--length" => options.length = Some(parse_value(option, value)?),
+            "--seed" => options.seed = Some(parse_value(option, value)?),
+            "--density" => options.density = parse_value(option, value)?,
+            "--uncompute" => options.uncompute = parse_uncompute(value)?,
+            "--format" => options.format = Format::parse(value)?,
+            _ => return Err(CliError::Usage(format!("unknown option {}", option))),
+        }
+    }
+    options.n = n.ok_or_else(|| CliError::Usage("select needs --n".to_string()))?;
+    Ok(options)
+}
+
+fn parse_args(args: &[String]) -> Result<Command, CliError> {
+    match args {
+        [command, rest @ ..] if command == "select" => Ok(Command::Select(parse_select(rest)?)),
+        [command, path] if command == "stats" => Ok(Command::Stats(path.clone())),
+        [command, input, output] if command == "convert" => {
+            Ok(Command::Convert(input.clone(), output.clone()))
+        }
+        _ => Err(CliError::Usage("unknown command".to_string())),
+    }
+}
+
+// 1行が1つのaddressで、各列がtarget qubitの0/1になる
+// 空行と#で始まる行は読み飛ばす
+// エラーの行番号はファイルの1から始まる行番号
+fn parse_table(text: &str) -> Result<Vec<Vec<bool>>, CliError> {
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line_number, line)| {
+            line.split(',')
+                .map(|cell| match cell.trim() {
+                    "0" => Ok(false),
+                    "1" => Ok(true),
+                    cell => Err(CliError::Usage(format!(
+                        "line {} of the data table has {:?}, expected 0 or 1",
+                        line_number, cell
+                    ))),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn read(path: &str) -> Result<String, CliError> {
+    fs::read_to_string(path).map_err(|error| CliError::Io(path.to_string(), error))
+}
+
+fn select(options: &SelectOptions) -> Result<String, CliError> {
+    check_length(options.n, options.length)?;
+    let circuit = match &options.data {
+        Some(path) => {
+            let data = parse_table(&read(path)?)?;
+            let width = data.first().map(|row| row.len()).unwrap_or(0);
+            // 行数が2^nより少なければ刈り取ったSELECTにする
+            let length = options.length.or_else(|| {
+                if options.n > 0 && options.n < 64 && data.len() < 1 << options.n {
+                    Some(data.len())
+                } else {
+                    None
+                }
+            });
+            uniform_layered_internal_with_data(options.n, length, width, &data, options.uncompute)?
+        }
+        None => {
+            check_density(options.density)?;
+            uniform_layered_internal(
+                options.n,
+                1,
+                options.length,
+                options.density,
+                options.seed,
+                options.uncompute,
+                1,
+            )?
+            .remove(0)
+        }
+    };
+    options.format.write(&circuit)
+}
+
+// qasmはCircuitStatsを、pyzxのjsonはT-depthを数える
+fn stats(path: &str) -> Result<String, CliError> {
+    let source = read(path)?;
+    if path.ends_with(".json") {
+        let pyzx: PyzxCircuitJson = serde_json::from_str(&source)?;
+        let plane = pyzx.produce_plane()?;
+        return Ok(format!("t_depth: {}", PyzxCircuitJson::count_depth(&plane)));
+    }
+    let stats = from_qasm(&source)?.stats().to_map();
+    let mut keys = stats.keys().collect::<Vec<_>>();
+    keys.sort();
+    Ok(keys
+        .iter()
+        .map(|key| format!("{}: {}", key, stats[*key]))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+fn convert(input: &str, output: &str) -> Result<(), CliError> {
+    let format = Format::from_path(output)?;
+    let circuit = from_qasm(&read(input)?)?;
+    fs::write(output, format.write(&circuit)?)
+        .map_err(|error| CliError::Io(output.to_string(), error))
+}
+
+fn run(command: &Command) -> Result<Option<String>, CliError> {
+    match command {
+        Command::Select(options) => select(options).map(Some),
+        Command::Stats(path) => stats(path).map(Some),
+        Command::Convert(input, output) => convert(input, output).map(|_| None),
+    }
+}
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    match parse_args(&args).and_then(|command| run(&command)) {
+        Ok(Some(output)) => {
+            // headなどで出力先が先に閉じても落ちないようにする
+            let _ = writeln!(io::stdout(), "{}", output);
+        }
+        Ok(None) => {}
+        Err(error) => {
+            eprintln!("q-divide: {}", error);
+            // 引数の誤りは2、それ以外の失敗は1で終わる
+            process::exit(match error {
+                CliError::Usage(_) => 2,
+                _ => 1,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+    #[test]
+    fn parse_commands() {
+        assert_eq!(
+            parse_args(&args("select --n 3 --format pyzx --uncompute measurement")).unwrap(),
+            Command::Select(SelectOptions {
+                n: 3,
+                data: None,
+                length: None,
+                seed: None,
+                density: 1.0,
+                uncompute: Uncompute::Measurement,
+                format: Format::Pyzx,
+            })
+        );
+        assert_eq!(
+            parse_args(&args("convert a.qasm b.json")).unwrap(),
+            Command::Convert("a.qasm".to_string(), "b.json".to_string())
+        );
+        assert!(matches!(
+            parse_args(&args("select --format qasm")),
+            Err(CliError::Usage(_))
+        ));
+        assert!(matches!(
+            parse_args(&args("select --n")),
+            Err(CliError::Usage(_))
+        ));
+        assert!(parse_args(&args("stats")).is_err());
+    }
+    #[test]
+    fn table_and_select() {
+        let table = parse_table("# address 0..2\n1,0\n0, 1\n\n1,1\n").unwrap();
+        assert_eq!(
+            table,
+            vec![vec![true, false], vec![false, true], vec![true, true]]
+        );
+        assert!(parse_table("1,2").is_err());
+        // 行番号はコメントと空行も数える
+        assert!(matches!(
+            parse_table("# header\n1,0\n\n0,x\n"),
+            Err(CliError::Usage(message)) if message.starts_with("line 4 of the data table")
+        ));
+        let options = SelectOptions {
+            n: 2,
+            data: None,
+            length: Some(3),
+            seed: Some(0),
+            density: 1.0,
+            uncompute: Uncompute::Toffoli,
+            format: Format::Qasm,
+        };
+        assert!(select(&options).unwrap().starts_with("OPENQASM 2.0;"));
+        assert!(matches!(
+            select(&SelectOptions {
+                length: Some(5),
+                ..options
+            }),
+            Err(CliError::Circuit(Error::InvalidArgument(_)))
+        ));
+    }
+}
@@ -0,0 +1,231 @@
+use crate::error::{Error, Result};
+use crate::gates::{ccx, cnot};
+use crate::primitive::{Angle, Circuit, PrimitiveGate, Qubit, QubitId};
+use crate::qasm::operations::{self, Operation};
+
+// controlが|1>のときだけcircuitを実行する回路を作る
+// controlは元の回路のqubitの後ろに追加し、qubitの名前とregisterはそのまま引き継ぐ
+// 各ゲートはシミュレータと同じ行列(大域位相を含む)の制御版に置き換える
+// 測定、リセット、古典制御は制御できない
+pub fn controlled(circuit: &Circuit, control: &str) -> Result<Circuit> {
+    let mut controlled = Circuit {
+        qubits: circuit.qubits.iter().map(|q| Qubit::new(&q.id)).collect(),
+        operations: Vec::new(),
+        registers: circuit.registers.clone(),
+        clbits: Vec::new(),
+    };
+    let control = controlled.add_qubit(control)?;
+    // Control/ControlledNotの依存関係はto_qasmで並べた順に従う
+    circuit
+        .to_qasm()
+        .operations
+        .iter()
+        .try_for_each(|operation| lift(&mut controlled, control, operation))?;
+    Ok(controlled)
+}
+
+fn qubit(qubit_id: operations::QubitId) -> QubitId {
+    QubitId(qubit_id.0 as usize)
+}
+
+// cyclomatic complexity: 1 + 13(match) = 14
+fn lift(circuit: &mut Circuit, control: QubitId, operation: &Operation) -> Result<()> {
+    match *operation {
+        Operation::X(target) => cnot(circuit, control, qubit(target)),
+        // toffoliはtargetが|0>でない場合に相対位相が残るので、正確なccxを使う
+        Operation::CX(from, to) => ccx(circuit, control, qubit(from), qubit(to)),
+        Operation::Z(target) => {
+            circuit.gate(qubit(target), PrimitiveGate::H);
+            cnot(circuit, control, qubit(target))?;
+            circuit.gate(qubit(target), PrimitiveGate::H);
+            Ok(())
+        }
+        Operation::S(target) => {
+            controlled_phase(circuit, control, qubit(target), Angle::PiRatio(1, 2))
+        }
+        Operation::SDag(target) => {
+            controlled_phase(circuit, control, qubit(target), Angle::PiRatio(-1, 2))
+        }
+        Operation::T(target) => {
+            controlled_phase(circuit, control, qubit(target), Angle::PiRatio(1, 4))
+        }
+        Operation::TDag(target) => {
+            controlled_phase(circuit, control, qubit(target), Angle::PiRatio(-1, 4))
+        }
+        Operation::H(target) => controlled_h(circuit, control, qubit(target)),
+        Operation::Rz(angle, target) => controlled_rz(circuit, control, qubit(target), angle),
+        Operation::Rx(angle, target) => {
+            circuit.gate(qubit(target), PrimitiveGate::H);
+            controlled_rz(circuit, control, qubit(target), angle)?;
+            circuit.gate(qubit(target), PrimitiveGate::H);
+            Ok(())
+        }
+        Operation::Ry(angle, target) => controlled_ry(circuit, control, qubit(target), angle),
+        // U(θ, φ, λ) = e^{i(φ+λ)/2} Rz(φ)Ry(θ)Rz(λ)
+        Operation::U(theta, phi, lambda, target) => {
+            controlled_rz(circuit, control, qubit(target), lambda)?;
+            controlled_ry(circuit, control, qubit(target), theta)?;
+            controlled_rz(circuit, control, qubit(target), phi)?;
+            circuit.gate(control, phase_gate((phi + lambda).halve()));
+            Ok(())
+        }
+        Operation::Measure(_, _) | Operation::Reset(_) | Operation::If(_, _, _) => {
+            Err(Error::Measurement("controlled"))
+        }
+    }
+}
+
+// diag(1, e^{iθ})になるゲート
+// T, S, Zで表せない角度は大域位相だけ異なるRzにする
+fn phase_gate(angle: Angle) -> PrimitiveGate {
+    match angle {
+        Angle::PiRatio(1, 4) => PrimitiveGate::T,
+        Angle::PiRatio(-1, 4) => PrimitiveGate::TDag,
+        Angle::PiRatio(1, 2) => PrimitiveGate::S,
+        Angle::PiRatio(-1, 2) => PrimitiveGate::SDag,
+        Angle::PiRatio(1, 1) | Angle::PiRatio(-1, 1) => PrimitiveGate::Z,
+        _ => PrimitiveGate::Rz(angle),
+    }
+}
+
+// controlとtargetが両方1のときだけe^{iθ}をかける
+// c t = (c + t - (c xor t)) / 2 を使う
+fn controlled_phase(
+    circuit: &mut Circuit,
+    control: QubitId,
+    target: QubitId,
+    angle: Angle,
+) -> Result<()> {
+    let half = angle.halve();
+    circuit.gate(control, phase_gate(half));
+    circuit.gate(target, phase_gate(half));
+    cnot(circuit, control, target)?;
+    circuit.gate(target, phase_gate(-half));
+    cnot(circuit, control, target)
+}
+
+// X Rz(-θ/2) X = Rz(θ/2)なので、controlが1のときだけRz(θ)になる
+fn controlled_rz(
+    circuit: &mut Circuit,
+    control: QubitId,
+    target: QubitId,
+    angle: Angle,
+) -> Result<()> {
+    let half = angle.halve();
+    circuit.gate(target, PrimitiveGate::Rz(half));
+    cnot(circuit, control, target)?;
+    circuit.gate(target, PrimitiveGate::Rz(-half));
+    cnot(circuit, control, target)
+}
+
+// X Ry(-θ/2) X = Ry(θ/2)なので、controlが1のときだけRy(θ)になる
+fn controlled_ry(
+    circuit: &mut Circuit,
+    control: QubitId,
+    target: QubitId,
+    angle: Angle,
+) -> Result<()> {
+    let half = angle.halve();
+    circuit.gate(target, PrimitiveGate::Ry(half));
+    cnot(circuit, control, target)?;
+    circuit.gate(target, PrimitiveGate::Ry(-half));
+    cnot(circuit, control, target)
+}
+
+// H = S H T X Tdg H Sdg のXを制御する
+fn controlled_h(circuit: &mut Circuit, control: QubitId, target: QubitId) -> Result<()> {
+    circuit.gate(target, PrimitiveGate::S);
+    circuit.gate(target, PrimitiveGate::H);
+    circuit.gate(target, PrimitiveGate::T);
+    cnot(circuit, control, target)?;
+    circuit.gate(target, PrimitiveGate::TDag);
+    circuit.gate(target, PrimitiveGate::H);
+    circuit.gate(target, PrimitiveGate::SDag);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gates::{mcx, toffoli, McxStrategy};
+    use crate::simulator::complex::Complex;
+    use crate::simulator::statevector::{simulate_file_from, StateVector};
+    use std::f64::consts::FRAC_1_SQRT_2;
+
+    // controlを|+>にしたとき、|0>側は入力のまま、|1>側は元の回路を通した状態になることを確かめる
+    fn assert_controlled(circuit: &Circuit) {
+        let n = circuit.qubits.len();
+        let original = circuit.to_qasm();
+        let mut file = controlled(circuit, "control").unwrap().to_qasm();
+        file.operations
+            .insert(0, Operation::H(operations::QubitId(n as i32)));
+        (0..1 << n).for_each(|input| {
+            let applied = simulate_file_from(&original, input).unwrap();
+            let mut expected = StateVector::new(n + 1).unwrap();
+            expected.amplitudes[0] = Complex::ZERO;
+            expected.amplitudes[input] = Complex::new(FRAC_1_SQRT_2, 0.0);
+            applied
+                .amplitudes
+                .iter()
+                .enumerate()
+                .for_each(|(index, amplitude)| {
+                    expected.amplitudes[(1 << n) | index] = amplitude.scale(FRAC_1_SQRT_2)
+                });
+            let actual = simulate_file_from(&file, input).unwrap();
+            assert!(expected.equals_up_to_global_phase(&actual), "{}", input);
+        });
+    }
+    #[test]
+    fn single_qubit_gates() {
+        let gates = [
+            PrimitiveGate::Z,
+            PrimitiveGate::H,
+            PrimitiveGate::X,
+            PrimitiveGate::T,
+            PrimitiveGate::TDag,
+            PrimitiveGate::S,
+            PrimitiveGate::SDag,
+            PrimitiveGate::Rz(Angle::pi_ratio(1, 3).unwrap()),
+            PrimitiveGate::Rx(Angle::Radians(0.7)),
+            PrimitiveGate::Ry(Angle::pi_ratio(-3, 4).unwrap()),
+            PrimitiveGate::U(
+                Angle::pi_ratio(1, 3).unwrap(),
+                Angle::pi_ratio(1, 5).unwrap(),
+                Angle::Radians(0.4),
+            ),
+        ];
+        gates.iter().for_each(|gate| {
+            let mut circuit = Circuit::new();
+            let q = circuit.add_qubit("q").unwrap();
+            // 位相の違いが見えるように重ね合わせにしておく
+            circuit.gate(q, PrimitiveGate::H);
+            circuit.gate(q, PrimitiveGate::T);
+            circuit.gate(q, *gate);
+            assert_controlled(&circuit);
+        });
+    }
+    #[test]
+    fn multi_qubit_circuit() {
+        let mut circuit = Circuit::new();
+        let qs = circuit.register("q", 4).unwrap();
+        circuit.gate(qs[0], PrimitiveGate::H);
+        toffoli(&mut circuit, qs[0], qs[1], qs[2]).unwrap();
+        mcx(&mut circuit, &qs[..3], qs[3], &[], McxStrategy::NoAncilla).unwrap();
+        circuit.gate(qs[3], PrimitiveGate::S);
+        let result = controlled(&circuit, "c").unwrap();
+        assert_eq!(result.qubits.last().unwrap().id, "c");
+        assert_eq!(result.find_register("q").unwrap().qubits, qs);
+        assert_controlled(&circuit);
+    }
+    #[test]
+    fn rejects_measurement() {
+        let mut circuit = Circuit::new();
+        let q = circuit.add_qubit("q").unwrap();
+        let clbit = circuit.add_clbit("c");
+        circuit.measure(q, clbit);
+        assert_eq!(
+            controlled(&circuit, "control").err(),
+            Some(Error::Measurement("controlled"))
+        );
+    }
+}
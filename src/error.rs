@@ -0,0 +1,85 @@
+use std::fmt;
+
+use crate::primitive::OpId;
+use crate::qasm::from_qasm::ParseError;
+use crate::select_gates::data_combine::DataTableError;
+use crate::simulator::statevector::StateVectorError;
+
+// crate全体で返すエラー
+// 各moduleのエラーはそのまま包んで持つ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    // 長さの不一致や範囲外など、引数が条件を満たしていない
+    InvalidArgument(String),
+    // Controlではない操作からCNOTを出そうとした
+    NotControl(OpId),
+    // 測定を含む回路には使えない変換(逆回路、制御版、PyZX)
+    Measurement(&'static str),
+    // PyZXのJSONとして読めない
+    Json(String),
+    // JSONとしては読めたが、PyZXの回路になっていない
+    MalformedPyzx(String),
+    Parse(ParseError),
+    StateVector(StateVectorError),
+    DataTable(DataTableError),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn invalid_argument(message: impl Into<String>) -> Error {
+        Error::InvalidArgument(message.into())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidArgument(message) => write!(f, "{}", message),
+            Error::NotControl(operation) => {
+                write!(f, "operation {} is not a control", operation.0)
+            }
+            Error::Measurement(action) => write!(f, "measurement cannot be {}", action),
+            Error::Json(message) => write!(f, "invalid json: {}", message),
+            Error::MalformedPyzx(message) => write!(f, "malformed pyzx circuit: {}", message),
+            Error::Parse(error) => write!(f, "{}", error),
+            Error::StateVector(error) => write!(f, "{}", error),
+            Error::DataTable(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Parse(error) => Some(error),
+            Error::StateVector(error) => Some(error),
+            Error::DataTable(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(error: ParseError) -> Error {
+        Error::Parse(error)
+    }
+}
+
+impl From<StateVectorError> for Error {
+    fn from(error: StateVectorError) -> Error {
+        Error::StateVector(error)
+    }
+}
+
+impl From<DataTableError> for Error {
+    fn from(error: DataTableError) -> Error {
+        Error::DataTable(error)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Error {
+        Error::Json(error.to_string())
+    }
+}
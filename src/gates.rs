@@ -1,58 +1,370 @@
+use super::error::{Error, Result};
 use super::primitive::*;
 
-pub fn cnot(q1: QubitCell, q2: QubitCell) {
-    let control_from = Qubit::control(q1);
-    let target = Qubit::export(q2);
-    target.control_by(&control_from);
+pub fn cnot(circuit: &mut Circuit, q1: QubitId, q2: QubitId) -> Result<()> {
+    let control_from = circuit.control(q1);
+    let target = circuit.export(q2);
+    target.control_by(circuit, &control_from)
 }
 
-pub fn toffoli(q1: QubitCell, q2: QubitCell, q3: QubitCell) {
-    Qubit::gate(q3.clone(), PrimitiveGate::H);
-    Qubit::gate(q3.clone(), PrimitiveGate::T);
+pub fn toffoli(circuit: &mut Circuit, q1: QubitId, q2: QubitId, q3: QubitId) -> Result<()> {
+    circuit.gate(q3, PrimitiveGate::H);
+    circuit.gate(q3, PrimitiveGate::T);
 
-    let control_from_2 = Qubit::control(q2);
-    let export_3_1 = Qubit::export(q3.clone());
-    export_3_1.control_by(&control_from_2);
+    let control_from_2 = circuit.control(q2);
+    let export_3_1 = circuit.export(q3);
+    export_3_1.control_by(circuit, &control_from_2)?;
 
-    Qubit::gate(q3.clone(), PrimitiveGate::TDag);
+    circuit.gate(q3, PrimitiveGate::TDag);
 
-    let control_from_1 = Qubit::control(q1);
-    let export_3_2 = Qubit::export(q3.clone());
-    export_3_2.control_by(&control_from_1);
+    let control_from_1 = circuit.control(q1);
+    let export_3_2 = circuit.export(q3);
+    export_3_2.control_by(circuit, &control_from_1)?;
 
-    Qubit::gate(q3.clone(), PrimitiveGate::T);
+    circuit.gate(q3, PrimitiveGate::T);
 
-    let export_3_3 = Qubit::export(q3.clone());
-    export_3_3.control_by(&control_from_2);
+    let export_3_3 = circuit.export(q3);
+    export_3_3.control_by(circuit, &control_from_2)?;
 
-    Qubit::gate(q3.clone(), PrimitiveGate::TDag);
+    circuit.gate(q3, PrimitiveGate::TDag);
 
-    Qubit::gate(q3.clone(), PrimitiveGate::H);
+    circuit.gate(q3, PrimitiveGate::H);
 
-    Qubit::gate(q3, PrimitiveGate::SDag);
+    circuit.gate(q3, PrimitiveGate::SDag);
+    Ok(())
 }
 
+// q3が|0>のときにq3 = q1 and q2を計算する
+pub fn toffoli_first_control(
+    circuit: &mut Circuit,
+    q1c: &ControlFrom,
+    q2: QubitId,
+    q3: QubitId,
+) -> Result<()> {
+    logical_and_first_control(circuit, q1c, q2, q3)
+}
+
+// q3が|0>のときにq3 = q1 and q2を計算する(T 4個)
+// Tはq3にだけかかるので、q1はcontrolとしてだけ使える
+// cyclomatic complexity: 1
+pub fn logical_and_first_control(
+    circuit: &mut Circuit,
+    q1c: &ControlFrom,
+    q2: QubitId,
+    q3: QubitId,
+) -> Result<()> {
+    circuit.gate(q3, PrimitiveGate::H);
+    circuit.gate(q3, PrimitiveGate::T);
+
+    let control_from_2 = circuit.control(q2);
+    let export_3_1 = circuit.export(q3);
+    export_3_1.control_by(circuit, &control_from_2)?;
+
+    circuit.gate(q3, PrimitiveGate::TDag);
+
+    let export_3_2 = circuit.export(q3);
+    export_3_2.control_by(circuit, q1c)?;
+
+    circuit.gate(q3, PrimitiveGate::T);
+
+    let export_3_3 = circuit.export(q3);
+    export_3_3.control_by(circuit, &control_from_2)?;
+
+    circuit.gate(q3, PrimitiveGate::TDag);
+
+    circuit.gate(q3, PrimitiveGate::H);
+
+    circuit.gate(q3, PrimitiveGate::SDag);
+    Ok(())
+}
+
+// toffoli_first_controlの逆回路
+// q3 = q1 and q2のときにq3を|0>に戻す
+// cyclomatic complexity: 1
+pub fn toffoli_first_control_dag(
+    circuit: &mut Circuit,
+    q1c: &ControlFrom,
+    q2: QubitId,
+    q3: QubitId,
+) -> Result<()> {
+    circuit.gate(q3, PrimitiveGate::S);
+
+    circuit.gate(q3, PrimitiveGate::H);
+
+    circuit.gate(q3, PrimitiveGate::T);
+
+    let control_from_2 = circuit.control(q2);
+    let export_3_1 = circuit.export(q3);
+    export_3_1.control_by(circuit, &control_from_2)?;
+
+    circuit.gate(q3, PrimitiveGate::TDag);
+
+    let export_3_2 = circuit.export(q3);
+    export_3_2.control_by(circuit, q1c)?;
+
+    circuit.gate(q3, PrimitiveGate::T);
+
+    let export_3_3 = circuit.export(q3);
+    export_3_3.control_by(circuit, &control_from_2)?;
+
+    circuit.gate(q3, PrimitiveGate::TDag);
+
+    circuit.gate(q3, PrimitiveGate::H);
+    Ok(())
+}
+
+// logical_and_first_controlを測定で戻す
+// q3 = q1 and q2のときにq3をX基底で測定し、結果が1ならCZ(q1, q2)で位相を直す
+// CZはq3にq1を一度コピーして、q3とq2の間で古典制御する
+// Tを使わない
 // cyclomatic complexity: 1
-pub fn toffoli_first_control(q1c: &ControlFrom, q2: QubitCell, q3: QubitCell) {
-    Qubit::gate(q3.clone(), PrimitiveGate::T);
+pub fn logical_and_uncompute_first_control(
+    circuit: &mut Circuit,
+    q1c: &ControlFrom,
+    q2: QubitId,
+    q3: QubitId,
+) -> Result<()> {
+    let clbit = circuit.add_clbit(&format!("uncompute_{}", circuit.clbits.len()));
 
-    let control_from_2 = Qubit::control(q2);
-    let export_3_1 = Qubit::export(q3.clone());
-    export_3_1.control_by(&control_from_2);
+    circuit.gate(q3, PrimitiveGate::H);
+    circuit.measure(q3, clbit);
+    circuit.conditioned_gate(q3, clbit, PrimitiveGate::X);
 
-    Qubit::gate(q3.clone(), PrimitiveGate::TDag);
+    // q3 = q1
+    let export_3_1 = circuit.export(q3);
+    export_3_1.control_by(circuit, q1c)?;
 
-    let export_3_2 = Qubit::export(q3.clone());
-    export_3_2.control_by(q1c);
+    // 測定結果が1のときだけCZ(q3, q2) = S q3, S q2, CX(q3, q2), Sdg q2, CX(q3, q2)
+    circuit.conditioned_gate(q3, clbit, PrimitiveGate::S);
+    circuit.conditioned_gate(q2, clbit, PrimitiveGate::S);
+    cnot(circuit, q3, q2)?;
+    circuit.conditioned_gate(q2, clbit, PrimitiveGate::SDag);
+    cnot(circuit, q3, q2)?;
 
-    Qubit::gate(q3.clone(), PrimitiveGate::T);
+    // q3を|0>に戻す
+    let export_3_2 = circuit.export(q3);
+    export_3_2.control_by(circuit, q1c)
+}
+
+// q3が|0>のときにq3 = q1 and q2を計算する(T 4個、T深さ2)
+// q3を|T>にしてからq1, q2, q3の3つにTを並べてかける
+// cyclomatic complexity: 1
+pub fn logical_and(circuit: &mut Circuit, q1: QubitId, q2: QubitId, q3: QubitId) -> Result<()> {
+    circuit.gate(q3, PrimitiveGate::H);
+    circuit.gate(q3, PrimitiveGate::T);
+
+    cnot(circuit, q1, q3)?;
+    cnot(circuit, q2, q3)?;
+    cnot(circuit, q3, q1)?;
+    cnot(circuit, q3, q2)?;
 
-    let export_3_3 = Qubit::export(q3.clone());
-    export_3_3.control_by(&control_from_2);
+    circuit.gate(q1, PrimitiveGate::TDag);
+    circuit.gate(q2, PrimitiveGate::TDag);
+    circuit.gate(q3, PrimitiveGate::T);
 
-    Qubit::gate(q3.clone(), PrimitiveGate::TDag);
+    cnot(circuit, q3, q1)?;
+    cnot(circuit, q3, q2)?;
 
-    Qubit::gate(q3.clone(), PrimitiveGate::H);
+    circuit.gate(q3, PrimitiveGate::H);
+    circuit.gate(q3, PrimitiveGate::S);
+    Ok(())
+}
+
+// logical_andの逆
+// q3 = q1 and q2のときにq3をX基底で測定して|0>に戻し、結果が1ならCZ(q1, q2)で位相を直す
+// Tを使わない
+// cyclomatic complexity: 1
+pub fn logical_and_uncompute(
+    circuit: &mut Circuit,
+    q1: QubitId,
+    q2: QubitId,
+    q3: QubitId,
+) -> Result<()> {
+    let clbit = circuit.add_clbit(&format!("uncompute_{}", circuit.clbits.len()));
+
+    circuit.gate(q3, PrimitiveGate::H);
+    circuit.measure(q3, clbit);
+    circuit.conditioned_gate(q3, clbit, PrimitiveGate::X);
+
+    // 測定結果が1のときだけCZ(q1, q2) = S q1, S q2, CX(q1, q2), Sdg q2, CX(q1, q2)
+    circuit.conditioned_gate(q1, clbit, PrimitiveGate::S);
+    circuit.conditioned_gate(q2, clbit, PrimitiveGate::S);
+    cnot(circuit, q1, q2)?;
+    circuit.conditioned_gate(q2, clbit, PrimitiveGate::SDag);
+    cnot(circuit, q1, q2)
+}
+
+// 任意の状態のq3に対して正確なToffoli(T 7個)
+// toffoliはq3が|0>でない場合に相対位相が残るので、ancillaやtargetが汚れている場合はこちらを使う
+// cyclomatic complexity: 1
+pub fn ccx(circuit: &mut Circuit, q1: QubitId, q2: QubitId, q3: QubitId) -> Result<()> {
+    circuit.gate(q3, PrimitiveGate::H);
+    cnot(circuit, q2, q3)?;
+    circuit.gate(q3, PrimitiveGate::TDag);
+    cnot(circuit, q1, q3)?;
+    circuit.gate(q3, PrimitiveGate::T);
+    cnot(circuit, q2, q3)?;
+    circuit.gate(q3, PrimitiveGate::TDag);
+    cnot(circuit, q1, q3)?;
+    circuit.gate(q2, PrimitiveGate::T);
+    circuit.gate(q3, PrimitiveGate::T);
+    circuit.gate(q3, PrimitiveGate::H);
+    cnot(circuit, q1, q2)?;
+    circuit.gate(q1, PrimitiveGate::T);
+    circuit.gate(q2, PrimitiveGate::TDag);
+    cnot(circuit, q1, q2)
+}
+
+// mcxでancillaをどう使うか
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum McxStrategy {
+    // |0>のancillaをcontrols.len() - 2個使い、ANDを順に計算して戻す
+    CleanVChain,
+    // 任意の状態のancillaをcontrols.len() - 2個使い、元の状態に戻す(Barenco et al. Lemma 7.2)
+    DirtyVChain,
+    // ancillaを使わず、controlsとtargetのパリティにπ/2^kの回転をかける(k = controls.len())
+    // 他のstrategyと違ってToffoliとCNOTだけでは作れないので、回転を使う
+    // (qubit数が4以上ではToffoliもCNOTも偶置換だが、mcxは奇置換になる。
+    //  Clifford+Tでもancillaなしでは正確に作れない)
+    // m = k + 1として、Rz(±π/2^k)を2^m - 1個、CNOTを(m - 2)2^m + 2個使う
+    // k >= 3では角度がClifford+Tに含まれず、T数では数えられない
+    // 使っていないqubitが1つでもあれば、DirtyVChainの方がずっと安い
+    // 大域位相を除いて等しい
+    NoAncilla,
+}
+
+impl McxStrategy {
+    // controlsの数に対して必要なancillaの数
+    pub fn ancilla_count(&self, control_count: usize) -> usize {
+        match self {
+            McxStrategy::CleanVChain | McxStrategy::DirtyVChain => control_count.saturating_sub(2),
+            McxStrategy::NoAncilla => 0,
+        }
+    }
+}
+
+// controlsがすべて1のときtargetを反転する
+// controlsが2個以下の場合はancillaもstrategyも使わない
+// controls, target, ancillasに同じqubitがあるとエラー
+// cyclomatic complexity: 1 + 6(condition) = 7
+pub fn mcx(
+    circuit: &mut Circuit,
+    controls: &[QubitId],
+    target: QubitId,
+    ancillas: &[QubitId],
+    strategy: McxStrategy,
+) -> Result<()> {
+    let qubits = controls
+        .iter()
+        .chain(std::iter::once(&target))
+        .chain(ancillas)
+        .collect::<Vec<_>>();
+    if let Some((_, duplicate)) = qubits
+        .iter()
+        .enumerate()
+        .find(|(i, q)| qubits[..*i].contains(q))
+    {
+        return Err(Error::invalid_argument(format!(
+            "qubit {} is used more than once in mcx",
+            duplicate.0
+        )));
+    }
+    let required = strategy.ancilla_count(controls.len());
+    if ancillas.len() < required {
+        return Err(Error::invalid_argument(format!(
+            "mcx with {} controls needs {} ancillas but {} were given",
+            controls.len(),
+            required,
+            ancillas.len()
+        )));
+    }
+    match controls {
+        [] => {
+            circuit.gate(target, PrimitiveGate::X);
+            Ok(())
+        }
+        [control] => cnot(circuit, *control, target),
+        [q1, q2] => ccx(circuit, *q1, *q2, target),
+        _ => match strategy {
+            McxStrategy::CleanVChain => mcx_clean(circuit, controls, target, ancillas),
+            McxStrategy::DirtyVChain => mcx_dirty(circuit, controls, target, ancillas),
+            McxStrategy::NoAncilla => mcx_no_ancilla(circuit, controls, target),
+        },
+    }
+}
+
+// ancillas[i] = controls[0] and ... and controls[i + 1]
+// ANDの計算はtoffoli(T 4個)、逆計算はtoffoli_first_control_dag(T 4個)で行う
+// cyclomatic complexity: 1 + 2(loop) = 3
+fn mcx_clean(
+    circuit: &mut Circuit,
+    controls: &[QubitId],
+    target: QubitId,
+    ancillas: &[QubitId],
+) -> Result<()> {
+    let last = controls.len() - 3;
+    toffoli(circuit, controls[0], controls[1], ancillas[0])?;
+    (1..=last).try_for_each(|i| toffoli(circuit, controls[i + 1], ancillas[i - 1], ancillas[i]))?;
+
+    ccx(circuit, controls[last + 2], ancillas[last], target)?;
+
+    (1..=last).rev().try_for_each(|i| {
+        let control = circuit.control(controls[i + 1]);
+        toffoli_first_control_dag(circuit, &control, ancillas[i - 1], ancillas[i])
+    })?;
+    let control = circuit.control(controls[0]);
+    toffoli_first_control_dag(circuit, &control, controls[1], ancillas[0])
+}
+
+// 上りと下りを2回繰り返すと、ancillaの元の値が打ち消し合う
+// cyclomatic complexity: 1 + 3(loop) = 4
+fn mcx_dirty(
+    circuit: &mut Circuit,
+    controls: &[QubitId],
+    target: QubitId,
+    ancillas: &[QubitId],
+) -> Result<()> {
+    let last = controls.len() - 3;
+    (0..2).try_for_each(|_| {
+        ccx(circuit, controls[last + 2], ancillas[last], target)?;
+        (1..=last)
+            .rev()
+            .try_for_each(|i| ccx(circuit, controls[i + 1], ancillas[i - 1], ancillas[i]))?;
+        ccx(circuit, controls[0], controls[1], ancillas[0])?;
+        (1..=last).try_for_each(|i| ccx(circuit, controls[i + 1], ancillas[i - 1], ancillas[i]))
+    })
+}
 
-    Qubit::gate(q3, PrimitiveGate::SDag);
+// x_1 x_2 ... x_m = Σ_S (-1)^(|S|+1) (⊕_{i∈S} x_i) / 2^(m-1) を使い
+// 各パリティを一番後ろのqubitに集めて位相をかける(Hで挟んでZをXにする)
+// cyclomatic complexity: 1 + 3(loop) = 4
+fn mcx_no_ancilla(circuit: &mut Circuit, controls: &[QubitId], target: QubitId) -> Result<()> {
+    let qubits = controls
+        .iter()
+        .copied()
+        .chain(std::iter::once(target))
+        .collect::<Vec<_>>();
+    let m = qubits.len();
+    circuit.gate(target, PrimitiveGate::H);
+    (1..1usize << m).try_for_each(|subset| {
+        let members = (0..m)
+            .filter(|i| subset >> i & 1 == 1)
+            .map(|i| qubits[i])
+            .collect::<Vec<_>>();
+        let (&collector, others) = members.split_last().unwrap();
+        let sign = if members.len() % 2 == 1 { 1 } else { -1 };
+        others
+            .iter()
+            .try_for_each(|&q| cnot(circuit, q, collector))?;
+        circuit.gate(
+            collector,
+            PrimitiveGate::Rz(Angle::pi_ratio_unchecked(sign, 1 << (m - 1))),
+        );
+        others
+            .iter()
+            .rev()
+            .try_for_each(|&q| cnot(circuit, q, collector))
+    })?;
+    circuit.gate(target, PrimitiveGate::H);
+    Ok(())
 }
@@ -1,25 +1,37 @@
+pub mod controlled;
+pub mod error;
 pub mod gates;
+pub mod metrics;
 pub mod primitive;
+// pythonのbindingは`python` featureを有効にした時だけbuildする
+#[cfg(feature = "python")]
 pub mod pyfunctions;
 pub mod pyzx;
 pub mod qasm;
 pub mod select_gates;
-pub mod util;
+pub mod simulator;
 
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
 
+#[cfg(feature = "python")]
 use pyfunctions::{
-    json_based::{count_t_depth, layered, uniform_layered},
+    circuit::PyCircuit,
+    json_based::{count_t_depth, layered, qasm_to_pyzx, uniform_layered},
     m_body::uniform_layered_m_body,
+    native::{layered_dict, qasm_operations, uniform_layered_dict, uniform_layered_operations},
     qasm_layered::{uniform_layered_qasm, uniform_layered_redundant},
+    simulation::{qasm_equivalent, simulate_qasm},
+    stats::{circuit_stats, uniform_layered_stats},
     tests::{output_json, sum_as_string, test_gate, test_gate_qasm},
 };
 
 /// A Python module implemented in Rust. The name of this function must match
 /// the `lib.name` setting in the `Cargo.toml`, else Python will not be able to
 /// import the module.
+#[cfg(feature = "python")]
 #[pymodule]
-fn prepare_circuit(_py: Python, m: &PyModule) -> PyResult<()> {
+fn prepare_circuit(py: Python, m: &PyModule) -> PyResult<()> {
     println!("prepare-circuit version 1.0.12");
     // tests
     m.add_function(wrap_pyfunction!(sum_as_string, m)?)?;
@@ -30,10 +42,26 @@ fn prepare_circuit(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(layered, m)?)?;
     m.add_function(wrap_pyfunction!(count_t_depth, m)?)?;
     m.add_function(wrap_pyfunction!(uniform_layered, m)?)?;
+    m.add_function(wrap_pyfunction!(qasm_to_pyzx, m)?)?;
     // qasm_layerd
     m.add_function(wrap_pyfunction!(uniform_layered_qasm, m)?)?;
     m.add_function(wrap_pyfunction!(uniform_layered_redundant, m)?)?;
     // m_body
     m.add_function(wrap_pyfunction!(uniform_layered_m_body, m)?)?;
+    // stats
+    m.add_function(wrap_pyfunction!(circuit_stats, m)?)?;
+    m.add_function(wrap_pyfunction!(uniform_layered_stats, m)?)?;
+    // simulation
+    m.add_function(wrap_pyfunction!(simulate_qasm, m)?)?;
+    m.add_function(wrap_pyfunction!(qasm_equivalent, m)?)?;
+    // native
+    m.add_function(wrap_pyfunction!(uniform_layered_dict, m)?)?;
+    m.add_function(wrap_pyfunction!(layered_dict, m)?)?;
+    m.add_function(wrap_pyfunction!(uniform_layered_operations, m)?)?;
+    m.add_function(wrap_pyfunction!(qasm_operations, m)?)?;
+    // circuit
+    m.add_class::<PyCircuit>()?;
+    // errors
+    pyfunctions::errors::register(py, m)?;
     Ok(())
 }
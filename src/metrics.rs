@@ -0,0 +1,181 @@
+// primitiveの回路から直接T-countやdepthを数える
+use crate::primitive::{Angle, Circuit, PrimitiveGate};
+use crate::qasm::operations::{File, Operation};
+use crate::qasm::to_qasm::to_qasm;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CircuitStats {
+    pub t_count: usize,
+    pub t_depth: usize,
+    pub cnot_count: usize,
+    pub cnot_depth: usize,
+    pub depth: usize,
+    pub qubit_count: usize,
+}
+
+impl CircuitStats {
+    pub fn to_map(&self) -> HashMap<String, usize> {
+        vec![
+            ("t_count", self.t_count),
+            ("t_depth", self.t_depth),
+            ("cnot_count", self.cnot_count),
+            ("cnot_depth", self.cnot_depth),
+            ("depth", self.depth),
+            ("qubit_count", self.qubit_count),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect()
+    }
+}
+
+// 各qubitとclbitについて、そこまでの層の数を持つ
+// clbitはqubitの後ろに並べ、測定からifへの依存も層として数える
+struct DepthCounter(Vec<usize>);
+
+impl DepthCounter {
+    // 関わるqubitを揃えて、数える対象なら1つ進める
+    fn apply(&mut self, qubits: &[usize], counted: bool) {
+        let level = qubits.iter().map(|q| self.0[*q]).max().unwrap_or(0) + counted as usize;
+        qubits.iter().for_each(|q| self.0[*q] = level);
+    }
+    fn depth(&self) -> usize {
+        self.0.iter().copied().max().unwrap_or(0)
+    }
+}
+
+// 操作が読み書きするqubitとclbit(qubit_countだけずらす)
+fn operation_wires(operation: &Operation, qubit_count: usize) -> Vec<usize> {
+    match operation {
+        Operation::CX(from, to) => vec![from.0 as usize, to.0 as usize],
+        Operation::Measure(target, clbit) | Operation::If(clbit, _, target) => {
+            vec![target.0 as usize, qubit_count + clbit.0]
+        }
+        _ => vec![operation.target().0 as usize],
+    }
+}
+
+// π/4の奇数倍のRzはTと同じだけのコストになる
+fn is_t_angle(angle: Angle) -> bool {
+    (angle + angle)
+        .quarter_turns()
+        .is_some_and(|turns| turns % 2 != 0)
+}
+
+fn is_t_gate(gate: PrimitiveGate) -> bool {
+    match gate {
+        PrimitiveGate::T | PrimitiveGate::TDag => true,
+        PrimitiveGate::Rz(angle) => is_t_angle(angle),
+        _ => false,
+    }
+}
+
+// operationsの順番はControl/ControlledNotの同期を守っているので、そのまま依存関係として使える
+pub fn file_stats(file: &File) -> CircuitStats {
+    let wire_count = file.qubit_count + file.clbit_count;
+    let mut t_layers = DepthCounter(vec![0; wire_count]);
+    let mut cnot_layers = DepthCounter(vec![0; wire_count]);
+    let mut layers = DepthCounter(vec![0; wire_count]);
+    let mut stats = CircuitStats {
+        qubit_count: file.qubit_count,
+        ..CircuitStats::default()
+    };
+    file.operations.iter().for_each(|operation| {
+        let qubits = operation_wires(operation, file.qubit_count);
+        // 古典制御されたTも実行されうるので数える
+        let is_t = match operation {
+            Operation::T(_) | Operation::TDag(_) => true,
+            Operation::Rz(angle, _) => is_t_angle(*angle),
+            Operation::If(_, gate, _) => is_t_gate(*gate),
+            _ => false,
+        };
+        let is_cnot = matches!(operation, Operation::CX(_, _));
+        stats.t_count += is_t as usize;
+        stats.cnot_count += is_cnot as usize;
+        t_layers.apply(&qubits, is_t);
+        cnot_layers.apply(&qubits, is_cnot);
+        layers.apply(&qubits, true);
+    });
+    stats.t_depth = t_layers.depth();
+    stats.cnot_depth = cnot_layers.depth();
+    stats.depth = layers.depth();
+    stats
+}
+
+pub fn circuit_stats(circuit: &Circuit) -> CircuitStats {
+    file_stats(&to_qasm(circuit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gates::toffoli;
+    use crate::select_gates::simple_select_controls::in_over_2n;
+    #[test]
+    fn toffoli_stats() {
+        let mut circuit = Circuit::new();
+        let q1 = circuit.add_qubit("q1").unwrap();
+        let q2 = circuit.add_qubit("q2").unwrap();
+        let q3 = circuit.add_qubit("q3").unwrap();
+        toffoli(&mut circuit, q1, q2, q3).unwrap();
+        let stats = circuit_stats(&circuit);
+        assert_eq!(
+            stats,
+            CircuitStats {
+                t_count: 4,
+                t_depth: 4,
+                cnot_count: 3,
+                cnot_depth: 3,
+                depth: 10,
+                qubit_count: 3,
+            }
+        );
+    }
+    #[test]
+    fn rz_quarter_turns_are_t() {
+        let mut circuit = Circuit::new();
+        let q = circuit.add_qubit("q").unwrap();
+        circuit.gate(q, PrimitiveGate::T);
+        circuit.gate(q, PrimitiveGate::Rz(Angle::pi_ratio(1, 4).unwrap()));
+        circuit.gate(q, PrimitiveGate::Rz(Angle::pi_ratio(-3, 4).unwrap()));
+        // π/2の倍数やπ/8はTではない
+        circuit.gate(q, PrimitiveGate::Rz(Angle::pi_ratio(1, 2).unwrap()));
+        circuit.gate(q, PrimitiveGate::Rz(Angle::pi_ratio(1, 8).unwrap()));
+        assert_eq!(circuit_stats(&circuit).t_count, 3);
+    }
+    #[test]
+    fn measurement_depth() {
+        // 測定とifは別のqubitにかかるが、clbitを通して順に並ぶ
+        let mut circuit = Circuit::new();
+        let measured = circuit.add_qubit("measured").unwrap();
+        let target = circuit.add_qubit("target").unwrap();
+        let clbit = circuit.add_clbit("m");
+        circuit.gate(measured, PrimitiveGate::H);
+        circuit.measure(measured, clbit);
+        circuit.conditioned_gate(target, clbit, PrimitiveGate::T);
+        let stats = circuit_stats(&circuit);
+        assert_eq!(stats.depth, 3);
+        assert_eq!(stats.t_count, 1);
+        assert_eq!(stats.t_depth, 1);
+    }
+    #[test]
+    fn select_t_count() {
+        let n = 3;
+        let mut circuit = Circuit::new();
+        let first = circuit.add_qubit("first").unwrap();
+        let first_control = circuit.control(first);
+        let datas = (0..n)
+            .map(|i| circuit.add_qubit(&format!("data_{}", i)).unwrap())
+            .collect::<Vec<_>>();
+        let ancillas = (0..n)
+            .map(|i| circuit.add_qubit(&format!("ancilla_{}", i)).unwrap())
+            .collect::<Vec<_>>();
+        in_over_2n(&mut circuit, n, &first_control, &datas, &ancillas).unwrap();
+        let stats = circuit_stats(&circuit);
+        // in_layerは2^n - 1個、それぞれT 8個
+        assert_eq!(stats.t_count, 8 * 7);
+        assert!(stats.t_depth > 0 && stats.t_depth <= stats.t_count);
+        assert_eq!(stats.qubit_count, 1 + 2 * n as usize);
+    }
+}
@@ -1,9 +1,12 @@
-use crate::util::*;
-use std::cell::RefCell;
-use std::hash::{Hash, Hasher};
-use std::rc::{Rc, Weak};
+use std::convert::TryFrom;
+use std::f64::consts::PI;
+use std::fmt;
+use std::ops::{Add, Neg};
 
-#[derive(Debug)]
+use crate::error::{Error, Result};
+use crate::{metrics, pyzx, qasm};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PrimitiveGate {
     Z,
     H,
@@ -12,70 +15,240 @@ pub enum PrimitiveGate {
     TDag,
     S,
     SDag,
+    Rz(Angle),
+    Rx(Angle),
+    Ry(Angle),
+    // U(θ, φ, λ) = Rz(φ)Ry(θ)Rz(λ)
+    U(Angle, Angle, Angle),
 }
 
-#[derive(Debug)]
-pub struct Operation {
-    pub id: usize,
-    pub parent: Weak<RefCell<Qubit>>,
-    pub node_type: NodeType,
+impl PrimitiveGate {
+    // 逆ゲート
+    pub fn inverse(self) -> PrimitiveGate {
+        match self {
+            PrimitiveGate::T => PrimitiveGate::TDag,
+            PrimitiveGate::TDag => PrimitiveGate::T,
+            PrimitiveGate::S => PrimitiveGate::SDag,
+            PrimitiveGate::SDag => PrimitiveGate::S,
+            PrimitiveGate::Rz(angle) => PrimitiveGate::Rz(-angle),
+            PrimitiveGate::Rx(angle) => PrimitiveGate::Rx(-angle),
+            PrimitiveGate::Ry(angle) => PrimitiveGate::Ry(-angle),
+            // (Rz(φ)Ry(θ)Rz(λ))† = Rz(-λ)Ry(-θ)Rz(-φ)
+            PrimitiveGate::U(theta, phi, lambda) => PrimitiveGate::U(-theta, -lambda, -phi),
+            PrimitiveGate::Z | PrimitiveGate::H | PrimitiveGate::X => self,
+        }
+    }
+}
+
+// i64::MINの絶対値もあふれないようにu64で計算する
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+// 分数を約分し、分母を正にする
+// 分母が0の場合と、i64::MINの符号を反転する必要がある場合はNone
+pub fn reduce(numerator: i64, denominator: i64) -> Option<(i64, i64)> {
+    let divisor = i64::try_from(gcd(numerator.unsigned_abs(), denominator.unsigned_abs())).ok()?;
+    let divisor = divisor.checked_mul(denominator.signum())?;
+    Some((
+        numerator.checked_div(divisor)?,
+        denominator.checked_div(divisor)?,
+    ))
+}
+
+// 回転角
+// πの有理数倍で表せるものは分数のまま持ち、それ以外はradianで持つ
+#[derive(Debug, Clone, Copy)]
+pub enum Angle {
+    // numerator * π / denominator (約分済みで、denominatorは正)
+    PiRatio(i64, i64),
+    Radians(f64),
+}
+
+impl Angle {
+    // numerator * π / denominator。分母が0ならエラー
+    pub fn pi_ratio(numerator: i64, denominator: i64) -> Result<Angle> {
+        if denominator == 0 {
+            return Err(Error::invalid_argument("denominator must not be 0"));
+        }
+        Ok(Angle::pi_ratio_unchecked(numerator, denominator))
+    }
+    // 分母が0でないことが分かっている場合に使う。0ならpanicする
+    pub fn pi_ratio_unchecked(numerator: i64, denominator: i64) -> Angle {
+        if denominator == 0 {
+            panic!("denominator must not be 0");
+        }
+        match reduce(numerator, denominator) {
+            Some((numerator, denominator)) => Angle::PiRatio(numerator, denominator),
+            // 約分しても分母を正にできない場合
+            None => Angle::Radians(PI * (numerator as f64) / (denominator as f64)),
+        }
+    }
+    // 半分の角度
+    pub fn halve(self) -> Angle {
+        match self {
+            Angle::PiRatio(numerator, denominator) => match denominator.checked_mul(2) {
+                Some(denominator) => Angle::pi_ratio_unchecked(numerator, denominator),
+                None => Angle::Radians(self.radians() / 2.0),
+            },
+            Angle::Radians(radians) => Angle::Radians(radians / 2.0),
+        }
+    }
+    pub fn radians(&self) -> f64 {
+        match self {
+            Angle::PiRatio(numerator, denominator) => {
+                PI * (*numerator as f64) / (*denominator as f64)
+            }
+            Angle::Radians(radians) => *radians,
+        }
+    }
+    // π/2の整数倍の場合はその倍数
+    pub fn quarter_turns(&self) -> Option<i64> {
+        match self {
+            Angle::PiRatio(numerator, 1) => Some(numerator * 2),
+            Angle::PiRatio(numerator, 2) => Some(*numerator),
+            _ => None,
+        }
+    }
+    // PyZXのphaseの文字列(単位はπ)。0の場合はNone
+    pub fn to_pyzx_string(&self) -> Option<String> {
+        match self {
+            Angle::PiRatio(0, _) => None,
+            Angle::PiRatio(numerator, denominator) => {
+                let numerator = match numerator {
+                    1 => "".to_string(),
+                    -1 => "-".to_string(),
+                    _ => numerator.to_string(),
+                };
+                let denominator = match denominator {
+                    1 => "".to_string(),
+                    _ => format!("/{}", denominator),
+                };
+                Some(format!("{}\\pi{}", numerator, denominator))
+            }
+            Angle::Radians(radians) => Some(format!("{}\\pi", radians / PI)),
+        }
+    }
+}
+
+// QASMの式として書き出す
+impl fmt::Display for Angle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Angle::PiRatio(0, _) => write!(f, "0"),
+            Angle::PiRatio(numerator, denominator) => {
+                match numerator {
+                    1 => write!(f, "pi")?,
+                    -1 => write!(f, "-pi")?,
+                    _ => write!(f, "{}*pi", numerator)?,
+                }
+                if *denominator != 1 {
+                    write!(f, "/{}", denominator)?;
+                }
+                Ok(())
+            }
+            Angle::Radians(radians) => write!(f, "{}", radians),
+        }
+    }
 }
-pub type OperationCell = Rc<RefCell<Operation>>;
-impl PartialEq for Operation {
+
+// Radians同士はbit列で比べるので、Eqにできる
+impl PartialEq for Angle {
     fn eq(&self, other: &Self) -> bool {
-        let parent_self = self.parent.upgrade();
-        let parent_other = other.parent.upgrade();
-        if let (Some(qcell_self), Some(qcell_other)) = (parent_self, parent_other) {
-            self.id == other.id && qcell_self == qcell_other
-        } else {
-            // parentが存在しない場合はfalse
-            false
+        match (self, other) {
+            (Angle::PiRatio(a, b), Angle::PiRatio(c, d)) => a == c && b == d,
+            (Angle::Radians(a), Angle::Radians(b)) => a.to_bits() == b.to_bits(),
+            _ => false,
         }
     }
 }
-impl Eq for Operation {}
-impl Hash for Operation {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.id.hash(state);
+
+impl Eq for Angle {}
+
+impl Add for Angle {
+    type Output = Angle;
+    fn add(self, other: Angle) -> Angle {
+        if let (Angle::PiRatio(a, b), Angle::PiRatio(c, d)) = (self, other) {
+            let sum = a
+                .checked_mul(d)
+                .zip(c.checked_mul(b))
+                .and_then(|(ad, cb)| ad.checked_add(cb))
+                .zip(b.checked_mul(d));
+            if let Some((numerator, denominator)) = sum {
+                return Angle::pi_ratio_unchecked(numerator, denominator);
+            }
+        }
+        Angle::Radians(self.radians() + other.radians())
     }
 }
 
-#[derive(Debug)]
+impl Neg for Angle {
+    type Output = Angle;
+    fn neg(self) -> Angle {
+        match self {
+            Angle::PiRatio(numerator, denominator) => match numerator.checked_neg() {
+                Some(numerator) => Angle::PiRatio(numerator, denominator),
+                None => Angle::Radians(-self.radians()),
+            },
+            Angle::Radians(radians) => Angle::Radians(-radians),
+        }
+    }
+}
+
+// Circuit.qubitsへのindex
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct QubitId(pub usize);
+
+// Circuit.operationsへのindex
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct OpId(pub usize);
+
+// Circuit.clbitsへのindex
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ClbitId(pub usize);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Operation {
+    // qubitの中で何番目の操作か(1始まり)
+    pub id: usize,
+    pub parent: QubitId,
+    pub node_type: NodeType,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum NodeType {
     PrimitiveGate(PrimitiveGate),
     PreControlledNot,
     // control total count
     Control(i32),
     // control from position
-    ControlledNot(Weak<RefCell<Operation>>, i32),
+    ControlledNot(OpId, i32),
+    // 計算基底で測定してclbitに書き込む
+    Measure(ClbitId),
+    // |0>に戻す
+    Reset,
+    // clbitが1のときだけゲートをかける
+    Conditioned(ClbitId, PrimitiveGate),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ControlFrom {
-    pub operation: OperationCell,
+    pub operation: OpId,
 }
 
 #[derive(Debug)]
 pub struct ControlTarget {
-    pub operation: OperationCell,
+    pub operation: OpId,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Qubit {
     pub id: String,
-    pub operations: Vec<OperationCell>,
-}
-pub type QubitCell = Rc<RefCell<Qubit>>;
-impl PartialEq for Qubit {
-    fn eq(&self, other: &Self) -> bool {
-        self.id == other.id
-    }
-}
-impl Eq for Qubit {}
-impl Hash for Qubit {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.id.hash(state);
-    }
+    pub operations: Vec<OpId>,
 }
 
 impl Qubit {
@@ -85,110 +258,452 @@ impl Qubit {
             operations: Vec::new(),
         }
     }
-    pub fn control(qcell: QubitCell) -> ControlFrom {
-        let length = qcell.borrow().operations.len();
-        let new_operation = cellize(Operation {
-            id: length + 1,
-            parent: Rc::downgrade(&qcell),
-            node_type: NodeType::Control(0),
+}
+
+// 名前付きでまとめて確保したqubitの並び
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Register {
+    pub name: String,
+    pub qubits: Vec<QubitId>,
+}
+
+// qubitと操作をまとめて持つarena
+// 参照はすべてindexなので、Rc/Weakを使わずにSendになる
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Circuit {
+    pub qubits: Vec<Qubit>,
+    pub operations: Vec<Operation>,
+    // 宣言した順
+    pub registers: Vec<Register>,
+    // 古典bitの名前
+    pub clbits: Vec<String>,
+}
+
+impl Circuit {
+    pub fn new() -> Circuit {
+        Circuit::default()
+    }
+    // qubitは追加した順に出力される
+    // PyZXはqubitを名前で区別するので、同じ名前のqubitは追加できない
+    pub fn add_qubit(&mut self, id: &str) -> Result<QubitId> {
+        if self.find_qubit(id).is_some() {
+            return Err(Error::invalid_argument(format!(
+                "qubit {} already exists",
+                id
+            )));
+        }
+        self.qubits.push(Qubit::new(id));
+        Ok(QubitId(self.qubits.len() - 1))
+    }
+    // name_0, name_1, ...という名前でsize個のqubitを確保する
+    // registerの名前もqubitの名前も既存のものと被ってはいけない
+    pub fn register(&mut self, name: &str, size: usize) -> Result<Vec<QubitId>> {
+        if self.find_register(name).is_some() {
+            return Err(Error::invalid_argument(format!(
+                "register {} already exists",
+                name
+            )));
+        }
+        let names = (0..size)
+            .map(|i| format!("{}_{}", name, i))
+            .collect::<Vec<_>>();
+        if let Some(id) = names.iter().find(|id| self.find_qubit(id).is_some()) {
+            return Err(Error::invalid_argument(format!(
+                "qubit {} already exists",
+                id
+            )));
+        }
+        let qubits = names
+            .iter()
+            .map(|id| self.add_qubit(id))
+            .collect::<Result<Vec<_>>>()?;
+        self.registers.push(Register {
+            name: name.to_string(),
+            qubits: qubits.clone(),
         });
-        qcell.borrow_mut().operations.push(new_operation.clone());
-        ControlFrom {
-            operation: new_operation,
+        Ok(qubits)
+    }
+    pub fn add_clbit(&mut self, id: &str) -> ClbitId {
+        self.clbits.push(id.to_string());
+        ClbitId(self.clbits.len() - 1)
+    }
+    pub fn find_register(&self, name: &str) -> Option<&Register> {
+        self.registers.iter().find(|register| register.name == name)
+    }
+    pub fn find_qubit(&self, id: &str) -> Option<QubitId> {
+        self.qubits.iter().position(|q| q.id == id).map(QubitId)
+    }
+    pub fn qubit(&self, qubit: QubitId) -> &Qubit {
+        &self.qubits[qubit.0]
+    }
+    pub fn operation(&self, operation: OpId) -> &Operation {
+        &self.operations[operation.0]
+    }
+    pub fn qubit_ids(&self) -> impl Iterator<Item = QubitId> {
+        (0..self.qubits.len()).map(QubitId)
+    }
+    pub fn qubit_ref(&self, qubit: QubitId) -> QubitRef<'_> {
+        QubitRef {
+            circuit: self,
+            id: qubit,
         }
     }
-    pub fn export(qcell: QubitCell) -> ControlTarget {
-        let length = qcell.borrow().operations.len();
-        let new_operation = cellize(Operation {
-            id: length + 1,
-            parent: Rc::downgrade(&qcell),
-            node_type: NodeType::PreControlledNot,
+    pub fn qubit_refs(&self) -> Vec<QubitRef<'_>> {
+        self.qubit_ids().map(|id| self.qubit_ref(id)).collect()
+    }
+    fn push(&mut self, qubit: QubitId, node_type: NodeType) -> OpId {
+        let operation_id = OpId(self.operations.len());
+        let qubit_operations = &mut self.qubits[qubit.0].operations;
+        self.operations.push(Operation {
+            id: qubit_operations.len() + 1,
+            parent: qubit,
+            node_type,
         });
-        qcell.borrow_mut().operations.push(new_operation.clone());
+        qubit_operations.push(operation_id);
+        operation_id
+    }
+    pub fn control(&mut self, qubit: QubitId) -> ControlFrom {
+        ControlFrom {
+            operation: self.push(qubit, NodeType::Control(0)),
+        }
+    }
+    pub fn export(&mut self, qubit: QubitId) -> ControlTarget {
         ControlTarget {
-            operation: new_operation,
+            operation: self.push(qubit, NodeType::PreControlledNot),
         }
     }
-    pub fn gate(qcell: QubitCell, gate: PrimitiveGate) {
-        let length = qcell.borrow().operations.len();
-        let new_operation = cellize(Operation {
-            id: length + 1,
-            parent: Rc::downgrade(&qcell),
-            node_type: NodeType::PrimitiveGate(gate),
+    pub fn gate(&mut self, qubit: QubitId, gate: PrimitiveGate) {
+        self.push(qubit, NodeType::PrimitiveGate(gate));
+    }
+    pub fn measure(&mut self, qubit: QubitId, clbit: ClbitId) {
+        self.push(qubit, NodeType::Measure(clbit));
+    }
+    pub fn reset(&mut self, qubit: QubitId) {
+        self.push(qubit, NodeType::Reset);
+    }
+    // clbitは直前(追加した順で)に書き込まれた測定結果を読む
+    pub fn conditioned_gate(&mut self, qubit: QubitId, clbit: ClbitId, gate: PrimitiveGate) {
+        self.push(qubit, NodeType::Conditioned(clbit, gate));
+    }
+    // 各ゲートをfの返すゲート列に置き換えた回路を作る
+    // ControlledNotが指すControlは新しい回路でのOpIdに付け替える
+    pub fn map_gates(&self, f: impl Fn(PrimitiveGate) -> Vec<PrimitiveGate>) -> Circuit {
+        let replaced = self
+            .operations
+            .iter()
+            .map(|operation| match operation.node_type {
+                NodeType::PrimitiveGate(gate) | NodeType::Conditioned(_, gate) => Some(f(gate)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let mut new_ids = Vec::with_capacity(replaced.len());
+        let mut next_id = 0;
+        replaced.iter().for_each(|gates| {
+            new_ids.push(OpId(next_id));
+            next_id += gates.as_ref().map_or(1, |gates| gates.len());
         });
-        qcell.borrow_mut().operations.push(new_operation);
+        let mut mapped = Circuit {
+            qubits: self.qubits.iter().map(|q| Qubit::new(&q.id)).collect(),
+            operations: Vec::with_capacity(next_id),
+            registers: self.registers.clone(),
+            clbits: self.clbits.clone(),
+        };
+        self.operations
+            .iter()
+            .zip(replaced)
+            .for_each(|(operation, gates)| match gates {
+                Some(gates) => gates.into_iter().for_each(|gate| {
+                    // 条件は分解した全てのゲートに付ける
+                    let node_type = match operation.node_type {
+                        NodeType::Conditioned(clbit, _) => NodeType::Conditioned(clbit, gate),
+                        _ => NodeType::PrimitiveGate(gate),
+                    };
+                    mapped.push(operation.parent, node_type);
+                }),
+                None => {
+                    let node_type = match operation.node_type {
+                        NodeType::ControlledNot(from, position) => {
+                            NodeType::ControlledNot(new_ids[from.0], position)
+                        }
+                        ref node_type => node_type.clone(),
+                    };
+                    mapped.push(operation.parent, node_type);
+                }
+            });
+        mapped
+    }
+    // 逆回路を作る
+    // 各qubitの操作列を逆順にして、ゲートを逆ゲートに置き換える
+    // 1つのControlから出るCNOTは逆の順で実行されるので、positionも反転する
+    // 測定、リセット、古典制御は逆にできない
+    pub fn inverse(&self) -> Result<Circuit> {
+        let mut inverted = Circuit {
+            qubits: self.qubits.iter().map(|q| Qubit::new(&q.id)).collect(),
+            operations: Vec::with_capacity(self.operations.len()),
+            registers: self.registers.clone(),
+            clbits: self.clbits.clone(),
+        };
+        let mut new_ids = vec![OpId(0); self.operations.len()];
+        for qubit in self.qubit_ids() {
+            for id in self.qubit(qubit).operations.iter().rev() {
+                let node_type = match &self.operation(*id).node_type {
+                    NodeType::PrimitiveGate(gate) => NodeType::PrimitiveGate(gate.inverse()),
+                    NodeType::Measure(_) | NodeType::Reset | NodeType::Conditioned(_, _) => {
+                        return Err(Error::Measurement("inverted"))
+                    }
+                    node_type => node_type.clone(),
+                };
+                new_ids[id.0] = inverted.push(qubit, node_type);
+            }
+        }
+        // Controlの新しいOpIdが揃ってから付け替える
+        for (index, operation) in self.operations.iter().enumerate() {
+            if let NodeType::ControlledNot(from, position) = operation.node_type {
+                let count = match self.operation(from).node_type {
+                    NodeType::Control(count) => count,
+                    _ => return Err(Error::NotControl(from)),
+                };
+                inverted.operations[new_ids[index].0].node_type =
+                    NodeType::ControlledNot(new_ids[from.0], count - 1 - position);
+            }
+        }
+        Ok(inverted)
+    }
+}
+
+impl Circuit {
+    pub fn to_qasm(&self) -> qasm::operations::File {
+        qasm::to_qasm::to_qasm(self)
+    }
+    pub fn to_qasm3(&self) -> qasm::to_qasm3::File {
+        qasm::to_qasm3::to_qasm3(self)
+    }
+    pub fn to_pyzx(&self) -> Result<pyzx::json::PyzxCircuitJson> {
+        pyzx::to_json::to_pyzx_circuit(self)
+    }
+    pub fn stats(&self) -> metrics::CircuitStats {
+        metrics::circuit_stats(self)
     }
 }
 
 impl ControlTarget {
-    pub fn control_by(self, control_from: &ControlFrom) {
-        let control_from_operation = control_from.operation.clone();
-        let mut control_from_operation = control_from_operation.borrow_mut();
+    pub fn control_by(self, circuit: &mut Circuit, control_from: &ControlFrom) -> Result<()> {
+        let control_from_operation = circuit
+            .operations
+            .get_mut(control_from.operation.0)
+            .ok_or(Error::NotControl(control_from.operation))?;
         if let NodeType::Control(count) = control_from_operation.node_type {
             control_from_operation.node_type = NodeType::Control(count + 1);
-            let operation = self.operation;
-            let mut operation = operation.borrow_mut();
-            operation.node_type =
-                NodeType::ControlledNot(Rc::downgrade(&control_from.operation), count);
+            circuit.operations[self.operation.0].node_type =
+                NodeType::ControlledNot(control_from.operation, count);
+            Ok(())
         } else {
-            panic!("control_from_operation is not control");
+            Err(Error::NotControl(control_from.operation))
         }
     }
 }
 
+// 回路の中のqubitを指す借用
+#[derive(Clone, Copy)]
+pub struct QubitRef<'a> {
+    pub circuit: &'a Circuit,
+    pub id: QubitId,
+}
+
+impl<'a> QubitRef<'a> {
+    pub fn qubit(&self) -> &'a Qubit {
+        self.circuit.qubit(self.id)
+    }
+    pub fn name(&self) -> &'a str {
+        &self.qubit().id
+    }
+    pub fn operations(&self) -> &'a [OpId] {
+        &self.qubit().operations
+    }
+    // qubitの中でindex番目の操作
+    pub fn operation(&self, index: usize) -> Option<OperationRef<'a>> {
+        self.operations().get(index).map(|id| OperationRef {
+            circuit: self.circuit,
+            id: *id,
+        })
+    }
+}
+
+impl PartialEq for QubitRef<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.circuit, other.circuit) && self.id == other.id
+    }
+}
+
+impl fmt::Debug for QubitRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "QubitRef({:?}, {})", self.id, self.name())
+    }
+}
+
+// 回路の中の操作を指す借用
+#[derive(Clone, Copy)]
+pub struct OperationRef<'a> {
+    pub circuit: &'a Circuit,
+    pub id: OpId,
+}
+
+impl<'a> OperationRef<'a> {
+    pub fn operation(&self) -> &'a Operation {
+        self.circuit.operation(self.id)
+    }
+    pub fn node_type(&self) -> &'a NodeType {
+        &self.operation().node_type
+    }
+    pub fn parent(&self) -> QubitRef<'a> {
+        self.circuit.qubit_ref(self.operation().parent)
+    }
+}
+
+impl PartialEq for OperationRef<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.circuit, other.circuit) && self.id == other.id
+    }
+}
+
+impl fmt::Debug for OperationRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "OperationRef({:?}, {:?})", self.id, self.node_type())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     #[test]
     fn control_not() {
-        let qcell1 = cellize(Qubit::new("1"));
-        let qcell2 = cellize(Qubit::new("2"));
-        let control_from = Qubit::control(qcell1.clone());
-        let control_target = Qubit::export(qcell2.clone());
-        control_target.control_by(&control_from);
-        println!("qcell1, {:?}", qcell1);
-        println!("qcell2, {:?}", qcell2);
-        let control_target_cell = qcell2;
-        let control_target_operation = &control_target_cell.borrow().operations[0];
-        let control_target_operation = control_target_operation.clone();
-        let control_target_node_type = &control_target_operation.borrow().node_type;
+        let mut circuit = Circuit::new();
+        let q1 = circuit.add_qubit("1").unwrap();
+        let q2 = circuit.add_qubit("2").unwrap();
+        let control_from = circuit.control(q1);
+        let control_target = circuit.export(q2);
+        control_target
+            .control_by(&mut circuit, &control_from)
+            .unwrap();
+        println!("circuit, {:?}", circuit);
+        let control_target_operation = circuit.qubit(q2).operations[0];
+        let control_target_node_type = &circuit.operation(control_target_operation).node_type;
         if let NodeType::ControlledNot(control_from, _) = control_target_node_type {
-            let control_from_operation = control_from.upgrade().unwrap();
-            let control_from_cell = control_from_operation.borrow().parent.upgrade().unwrap();
-            assert_eq!(control_from_cell, qcell1);
+            assert_eq!(circuit.operation(*control_from).parent, q1);
+        } else {
+            panic!("export is not controlled");
         }
     }
     #[test]
     fn double_control() {
-        let qcell1 = cellize(Qubit::new("1"));
-        let qcell2 = cellize(Qubit::new("2"));
-        let control_from = Qubit::control(qcell1.clone());
-        let control_target1 = Qubit::export(qcell2.clone());
-        let control_target2 = Qubit::export(qcell2.clone());
-        control_target1.control_by(&control_from);
-        control_target2.control_by(&control_from);
-        println!("qcell1, {:?}", qcell1);
-        println!("qcell2, {:?}", qcell2);
-        let control_target1_cell = qcell2;
-        let operation_len = control_target1_cell.borrow().operations.len();
+        let mut circuit = Circuit::new();
+        let q1 = circuit.add_qubit("1").unwrap();
+        let q2 = circuit.add_qubit("2").unwrap();
+        let control_from = circuit.control(q1);
+        let control_target1 = circuit.export(q2);
+        let control_target2 = circuit.export(q2);
+        control_target1
+            .control_by(&mut circuit, &control_from)
+            .unwrap();
+        control_target2
+            .control_by(&mut circuit, &control_from)
+            .unwrap();
+        println!("circuit, {:?}", circuit);
+        let operation_len = circuit.qubit(q2).operations.len();
         assert_eq!(operation_len, 2);
     }
     #[test]
     fn control_count() {
-        let qcell1 = cellize(Qubit::new("1"));
-        let qcell2 = cellize(Qubit::new("2"));
-        let control_from = Qubit::control(qcell1.clone());
-        let control_target1 = Qubit::export(qcell2.clone());
-        let control_target2 = Qubit::export(qcell2);
-        control_target1.control_by(&control_from);
-        control_target2.control_by(&control_from);
-        let control_from_cell = qcell1;
-        let control_from_operation = &control_from_cell.borrow().operations[0];
-        let control_from_operation_node_type = &control_from_operation.borrow().node_type;
-        let count = match control_from_operation_node_type {
-            &NodeType::Control(count) => Some(count),
+        let mut circuit = Circuit::new();
+        let q1 = circuit.add_qubit("1").unwrap();
+        let q2 = circuit.add_qubit("2").unwrap();
+        let control_from = circuit.control(q1);
+        let control_target1 = circuit.export(q2);
+        let control_target2 = circuit.export(q2);
+        control_target1
+            .control_by(&mut circuit, &control_from)
+            .unwrap();
+        control_target2
+            .control_by(&mut circuit, &control_from)
+            .unwrap();
+        let control_from_operation = circuit.qubit(q1).operations[0];
+        let count = match circuit.operation(control_from_operation).node_type {
+            NodeType::Control(count) => Some(count),
             _ => None,
         };
         assert_eq!(count, Some(2));
     }
+    #[test]
+    fn control_by_not_control() {
+        let mut circuit = Circuit::new();
+        let q1 = circuit.add_qubit("1").unwrap();
+        let q2 = circuit.add_qubit("2").unwrap();
+        circuit.gate(q1, PrimitiveGate::H);
+        let not_control = ControlFrom {
+            operation: circuit.qubit(q1).operations[0],
+        };
+        let control_target = circuit.export(q2);
+        assert_eq!(
+            control_target.control_by(&mut circuit, &not_control),
+            Err(Error::NotControl(not_control.operation))
+        );
+        let control_target = circuit.export(q2);
+        let out_of_range = ControlFrom { operation: OpId(9) };
+        assert!(control_target
+            .control_by(&mut circuit, &out_of_range)
+            .is_err());
+    }
+    #[test]
+    fn register_order() {
+        let mut circuit = Circuit::new();
+        let datas = circuit.register("data", 2).unwrap();
+        let first = circuit.add_qubit("first").unwrap();
+        let targets = circuit.register("target", 1).unwrap();
+        let names = circuit
+            .qubits
+            .iter()
+            .map(|q| q.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["data_0", "data_1", "first", "target_0"]);
+        assert_eq!(datas, vec![QubitId(0), QubitId(1)]);
+        assert_eq!(first, QubitId(2));
+        assert_eq!(circuit.find_register("target").unwrap().qubits, targets);
+        assert!(circuit.find_register("ancilla").is_none());
+    }
+    #[test]
+    fn duplicate_names() {
+        let mut circuit = Circuit::new();
+        circuit.register("data", 2).unwrap();
+        assert!(circuit.add_qubit("data_1").is_err());
+        assert!(circuit.register("data", 1).is_err());
+        circuit.add_qubit("target_0").unwrap();
+        assert!(circuit.register("target", 1).is_err());
+        // 失敗した場合は何も追加しない
+        assert_eq!(circuit.qubits.len(), 3);
+        assert!(circuit.find_register("target").is_none());
+        assert_eq!(circuit.find_qubit("data_1"), Some(QubitId(1)));
+    }
+    #[test]
+    fn angle_format() {
+        assert_eq!(Angle::pi_ratio(2, -8).unwrap(), Angle::PiRatio(-1, 4));
+        assert_eq!(Angle::pi_ratio(2, -8).unwrap().to_string(), "-pi/4");
+        assert_eq!(Angle::pi_ratio(6, 4).unwrap().to_string(), "3*pi/2");
+        assert_eq!(Angle::Radians(0.5).to_string(), "0.5");
+        assert_eq!(
+            Angle::pi_ratio(3, 4).unwrap().to_pyzx_string(),
+            Some("3\\pi/4".to_string())
+        );
+        assert_eq!(Angle::pi_ratio(0, 3).unwrap().to_pyzx_string(), None);
+        let sum = Angle::pi_ratio(1, 4).unwrap() + Angle::pi_ratio(1, 4).unwrap();
+        assert_eq!(sum.quarter_turns(), Some(1));
+        assert_eq!(
+            Angle::pi_ratio(1, 0),
+            Err(Error::invalid_argument("denominator must not be 0"))
+        );
+    }
+    #[test]
+    fn circuit_is_send() {
+        fn assert_send<T: Send + Sync>() {}
+        assert_send::<Circuit>();
+    }
 }
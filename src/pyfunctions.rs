@@ -1,5 +1,9 @@
-mod internal;
+pub mod circuit;
+pub mod errors;
 pub mod json_based;
 pub mod m_body;
+pub mod native;
 pub mod qasm_layered;
+pub mod simulation;
+pub mod stats;
 pub mod tests;
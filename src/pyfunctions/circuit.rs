@@ -0,0 +1,364 @@
+// pythonからゲートを1つずつ積んで回路を作るためのclass
+// qubitはadd_qubit/registerが返すindexで指定する
+// pyo3 0.16の#[pymethods]は関数の中にimplを展開するので、その警告だけ抑える
+#![allow(non_local_definitions)]
+
+use crate::error::{Error, Result};
+use crate::gates::{ccx, cnot};
+use crate::primitive::{self, PrimitiveGate, QubitId};
+use crate::qasm::operations::OperationTuple;
+use crate::select_gates::data_combine::{inject_qrom_datas, validate_datas};
+use crate::select_gates::layered::{parse_uncompute, select_length};
+use crate::select_gates::simple_select_controls::select_controls;
+
+use super::native::pyzx_to_dict;
+
+use pyo3::prelude::*;
+
+/// a circuit built gate by gate; qubits are referred to by the index returned from `add_qubit`
+#[pyclass(name = "Circuit")]
+#[derive(Debug, Clone, Default)]
+pub struct PyCircuit {
+    pub circuit: primitive::Circuit,
+}
+
+impl PyCircuit {
+    fn qubit(&self, index: usize) -> Result<QubitId> {
+        if index < self.circuit.qubits.len() {
+            Ok(QubitId(index))
+        } else {
+            Err(Error::invalid_argument(format!(
+                "qubit {} is out of range for {} qubits",
+                index,
+                self.circuit.qubits.len()
+            )))
+        }
+    }
+    // 同じqubitを2回指定するとCNOTが自分自身を制御してしまう
+    fn distinct_qubits(&self, indices: &[usize]) -> Result<Vec<QubitId>> {
+        let qubits = indices
+            .iter()
+            .map(|index| self.qubit(*index))
+            .collect::<Result<Vec<_>>>()?;
+        let distinct = qubits
+            .iter()
+            .enumerate()
+            .all(|(i, q)| qubits[..i].iter().all(|other| other != q));
+        if distinct {
+            Ok(qubits)
+        } else {
+            Err(Error::invalid_argument(format!(
+                "qubits {:?} must be distinct",
+                indices
+            )))
+        }
+    }
+    fn gate(&mut self, qubit: usize, gate: PrimitiveGate) -> Result<()> {
+        let qubit = self.qubit(qubit)?;
+        self.circuit.gate(qubit, gate);
+        Ok(())
+    }
+    fn register_internal(&mut self, name: &str, size: usize) -> Result<Vec<usize>> {
+        Ok(self
+            .circuit
+            .register(name, size)?
+            .into_iter()
+            .map(|q| q.0)
+            .collect())
+    }
+    fn cx_internal(&mut self, control: usize, target: usize) -> Result<()> {
+        let qubits = self.distinct_qubits(&[control, target])?;
+        cnot(&mut self.circuit, qubits[0], qubits[1])
+    }
+    fn ccx_internal(&mut self, control_1: usize, control_2: usize, target: usize) -> Result<()> {
+        let qubits = self.distinct_qubits(&[control_1, control_2, target])?;
+        ccx(&mut self.circuit, qubits[0], qubits[1], qubits[2])
+    }
+    // selectで確保するfirst, ancilla, targetの名前の後ろに付ける番号
+    // 2回目以降のselectで名前が被ると、PyZXで同じqubitとして扱われてしまう
+    fn select_suffix(&self, n: usize, width: usize, new_first: bool) -> String {
+        let unused_register = |name: &str, size: usize| {
+            self.circuit.find_register(name).is_none()
+                && (0..size).all(|i| {
+                    self.circuit
+                        .find_qubit(&format!("{}_{}", name, i))
+                        .is_none()
+                })
+        };
+        (0..)
+            .map(|k: usize| if k == 0 { String::new() } else { k.to_string() })
+            .find(|suffix| {
+                unused_register(&format!("ancilla{}", suffix), n)
+                    && unused_register(&format!("target{}", suffix), width)
+                    && (!new_first
+                        || self
+                            .circuit
+                            .find_qubit(&format!("first{}", suffix))
+                            .is_none())
+            })
+            .unwrap()
+    }
+    // addressはcontrols[0]を最上位bitとして読む
+    // ancillaとtargetは新しく確保し、firstがなければ|1>にした新しいqubitを使う
+    // 引数はすべて回路を変更する前に確かめるので、失敗しても回路はそのまま残る
+    // cyclomatic complexity: 1 + 6(condition) = 7
+    fn select_internal(
+        &mut self,
+        n: i32,
+        controls: &[usize],
+        data: &[Vec<bool>],
+        first: Option<usize>,
+        uncompute: &str,
+    ) -> Result<Vec<usize>> {
+        let uncompute = parse_uncompute(uncompute)?;
+        if n <= 0 {
+            return Err(Error::invalid_argument("n must be greater than 0"));
+        }
+        let rows = select_length(n, None)?;
+        if controls.len() != n as usize {
+            return Err(Error::invalid_argument(format!(
+                "controls must have n = {} qubits, got {}",
+                n,
+                controls.len()
+            )));
+        }
+        if data.is_empty() || data.len() > rows {
+            return Err(Error::invalid_argument(format!(
+                "data must have between 1 and 2^n rows, got {}",
+                data.len()
+            )));
+        }
+        let datas = self.distinct_qubits(controls)?;
+        let width = data[0].len();
+        validate_datas(data, data.len(), width)?;
+        let existing_first = first.map(|first| self.qubit(first)).transpose()?;
+        if existing_first.is_some_and(|first| datas.contains(&first)) {
+            return Err(Error::invalid_argument("first must not be one of controls"));
+        }
+        let suffix = self.select_suffix(n as usize, width, first.is_none());
+        let first = match existing_first {
+            Some(first) => first,
+            None => {
+                let first = self.circuit.add_qubit(&format!("first{}", suffix))?;
+                self.circuit.gate(first, PrimitiveGate::X);
+                first
+            }
+        };
+        let ancillas = self
+            .circuit
+            .register(&format!("ancilla{}", suffix), n as usize)?;
+        let targets = self.circuit.register(&format!("target{}", suffix), width)?;
+        let first_control = self.circuit.control(first);
+        let length = if data.len() == rows {
+            None
+        } else {
+            Some(data.len())
+        };
+        let select = select_controls(
+            &mut self.circuit,
+            n,
+            length,
+            &first_control,
+            &datas,
+            &ancillas,
+            uncompute,
+        )?;
+        inject_qrom_datas(&mut self.circuit, &targets, &select, data)?;
+        Ok(targets.iter().map(|q| q.0).collect())
+    }
+}
+
+#[pymethods]
+impl PyCircuit {
+    #[new]
+    pub fn new() -> Self {
+        PyCircuit::default()
+    }
+    /// adds a qubit named `id` and returns its index; `id` must not be in use
+    #[pyo3(text_signature = "($self, id, /)")]
+    pub fn add_qubit(&mut self, id: &str) -> PyResult<usize> {
+        Ok(self.circuit.add_qubit(id)?.0)
+    }
+    /// adds `size` qubits named `name_0`, `name_1`, ... and returns their indices
+    ///
+    /// Neither the register name nor the qubit names may be in use.
+    #[pyo3(text_signature = "($self, name, size, /)")]
+    pub fn register(&mut self, name: &str, size: usize) -> PyResult<Vec<usize>> {
+        Ok(self.register_internal(name, size)?)
+    }
+    #[getter]
+    pub fn qubit_count(&self) -> usize {
+        self.circuit.qubits.len()
+    }
+    pub fn h(&mut self, qubit: usize) -> PyResult<()> {
+        Ok(self.gate(qubit, PrimitiveGate::H)?)
+    }
+    pub fn x(&mut self, qubit: usize) -> PyResult<()> {
+        Ok(self.gate(qubit, PrimitiveGate::X)?)
+    }
+    pub fn z(&mut self, qubit: usize) -> PyResult<()> {
+        Ok(self.gate(qubit, PrimitiveGate::Z)?)
+    }
+    pub fn t(&mut self, qubit: usize) -> PyResult<()> {
+        Ok(self.gate(qubit, PrimitiveGate::T)?)
+    }
+    pub fn tdg(&mut self, qubit: usize) -> PyResult<()> {
+        Ok(self.gate(qubit, PrimitiveGate::TDag)?)
+    }
+    pub fn s(&mut self, qubit: usize) -> PyResult<()> {
+        Ok(self.gate(qubit, PrimitiveGate::S)?)
+    }
+    pub fn sdg(&mut self, qubit: usize) -> PyResult<()> {
+        Ok(self.gate(qubit, PrimitiveGate::SDag)?)
+    }
+    #[pyo3(text_signature = "($self, control, target, /)")]
+    pub fn cx(&mut self, control: usize, target: usize) -> PyResult<()> {
+        Ok(self.cx_internal(control, target)?)
+    }
+    /// exact Toffoli with 7 T gates
+    #[pyo3(text_signature = "($self, control_1, control_2, target, /)")]
+    pub fn ccx(&mut self, control_1: usize, control_2: usize, target: usize) -> PyResult<()> {
+        Ok(self.ccx_internal(control_1, control_2, target)?)
+    }
+    /// writes `data[address]` into a new target register, reading the address from the `n` qubits of `controls`
+    ///
+    /// `controls[0]` is the most significant bit. `data` may have fewer than `2^n` rows.
+    /// New `ancilla` and `target` registers are added; the target indices are returned.
+    /// When these names are already in use, a number is appended (`ancilla1`, `target1`, ...).
+    /// The SELECT is enabled by `first`, or unconditionally when `first` is omitted.
+    #[args(first = "None", uncompute = "\"toffoli\"")]
+    #[pyo3(text_signature = "($self, n, controls, data, /, first=None, uncompute=\"toffoli\")")]
+    pub fn select(
+        &mut self,
+        n: i32,
+        controls: Vec<usize>,
+        data: Vec<Vec<bool>>,
+        first: Option<usize>,
+        uncompute: &str,
+    ) -> PyResult<Vec<usize>> {
+        Ok(self.select_internal(n, &controls, &data, first, uncompute)?)
+    }
+    /// `version=3` writes OpenQASM 3 with named registers instead of OpenQASM 2.0
+    #[args(version = "2")]
+    #[pyo3(text_signature = "($self, /, version=2)")]
+    pub fn to_qasm(&self, version: u8) -> PyResult<String> {
+        match version {
+            2 => Ok(self.circuit.to_qasm().to_string()),
+            3 => Ok(self.circuit.to_qasm3().to_string()),
+            _ => Err(
+                Error::invalid_argument(format!("version must be 2 or 3, got {}", version)).into(),
+            ),
+        }
+    }
+    pub fn to_pyzx_json(&self) -> PyResult<String> {
+        let pyzx_json = self.circuit.to_pyzx()?;
+        Ok(serde_json::to_string(&pyzx_json).map_err(Error::from)?)
+    }
+    pub fn to_pyzx_dict(&self, py: Python) -> PyResult<PyObject> {
+        pyzx_to_dict(py, &self.circuit.to_pyzx()?)
+    }
+    /// the operations in qasm order as `(gate_name, qubits, params, clbits)` tuples
+    pub fn operations(&self) -> Vec<OperationTuple> {
+        self.circuit
+            .to_qasm()
+            .operations
+            .iter()
+            .map(|operation| operation.to_tuple())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::basis::simulate;
+    #[test]
+    fn build_gate_by_gate() {
+        let mut circuit = PyCircuit::new();
+        let q = circuit.register_internal("q", 2).unwrap();
+        // PyResultを返すメソッドはpythonにリンクしないと呼べないので、中身を直接使う
+        circuit.gate(q[0], PrimitiveGate::H).unwrap();
+        circuit.cx_internal(q[0], q[1]).unwrap();
+        let qasm_string = "OPENQASM 2.0;\n".to_string()
+            + "include \"qelib1.inc\";\n"
+            + "qreg q[2];\n"
+            + "h q[0];\n"
+            + "cx q[0], q[1];";
+        assert_eq!(circuit.circuit.to_qasm().to_string(), qasm_string);
+        assert!(circuit.gate(2, PrimitiveGate::T).is_err());
+        assert!(circuit.ccx_internal(0, 1, 0).is_err());
+    }
+    #[test]
+    fn select_writes_table() {
+        let mut circuit = PyCircuit::new();
+        let address = circuit.register_internal("address", 2).unwrap();
+        let data = vec![vec![false, true], vec![true, false], vec![true, true]];
+        let targets = circuit
+            .select_internal(2, &address, &data, None, "toffoli")
+            .unwrap();
+        assert_eq!(targets.len(), 2);
+        data.iter().enumerate().for_each(|(row, expected)| {
+            let mut initial = vec![false; circuit.qubit_count()];
+            initial[address[0]] = row & 2 == 2;
+            initial[address[1]] = row & 1 == 1;
+            let simulator = simulate(&circuit.circuit, initial);
+            let (state, _) = simulator.single_state().unwrap();
+            let actual = targets.iter().map(|q| state[*q]).collect::<Vec<_>>();
+            assert_eq!(&actual, expected);
+        });
+        assert!(circuit
+            .select_internal(1, &address[..1], &data, None, "toffoli")
+            .is_err());
+        assert!(circuit
+            .select_internal(64, &address, &data, None, "toffoli")
+            .is_err());
+    }
+    #[test]
+    fn rejected_select_leaves_circuit() {
+        let mut circuit = PyCircuit::new();
+        let address = circuit.register_internal("address", 2).unwrap();
+        let before = circuit.circuit.clone();
+        let data = vec![vec![true], vec![false]];
+        // controlsの数がnと違う
+        assert!(circuit
+            .select_internal(3, &address, &data, None, "toffoli")
+            .is_err());
+        // firstがcontrolsに含まれる
+        assert!(circuit
+            .select_internal(2, &address, &data, Some(address[0]), "toffoli")
+            .is_err());
+        // 行の幅がそろっていない
+        assert!(circuit
+            .select_internal(2, &address, &[vec![true], vec![]], None, "toffoli")
+            .is_err());
+        assert!(circuit
+            .select_internal(2, &address, &data, None, "unknown")
+            .is_err());
+        assert_eq!(circuit.circuit, before);
+    }
+    #[test]
+    fn repeated_select_names() {
+        let mut circuit = PyCircuit::new();
+        let address = circuit.register_internal("address", 1).unwrap();
+        let data = vec![vec![true], vec![false]];
+        let first = circuit
+            .select_internal(1, &address, &data, None, "toffoli")
+            .unwrap();
+        let second = circuit
+            .select_internal(1, &address, &data, None, "toffoli")
+            .unwrap();
+        assert_eq!(circuit.circuit.qubits[first[0]].id, "target_0");
+        assert_eq!(circuit.circuit.qubits[second[0]].id, "target1_0");
+        assert!(circuit.circuit.find_qubit("first1").is_some());
+        // PyZXはqubitを名前で区別するので、qubitごとに入力と出力がある
+        let inputs = circuit
+            .circuit
+            .to_pyzx()
+            .unwrap()
+            .wire_vertices
+            .values()
+            .filter(|vertex| vertex.annotation.input)
+            .count();
+        assert_eq!(inputs, circuit.qubit_count());
+    }
+}
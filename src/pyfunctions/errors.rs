@@ -0,0 +1,49 @@
+// crate::error::Errorをpythonの例外に変換する
+// すべてCircuitError(ValueErrorのサブクラス)を継承するので、既存のexcept ValueErrorでも捕まえられる
+// pyo3 0.16のcreate_exception!は今のrustcが知らないcfgを展開するので、その警告だけ抑える
+#![allow(unexpected_cfgs)]
+
+use crate::error::Error;
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+create_exception!(prepare_circuit, CircuitError, PyValueError);
+create_exception!(prepare_circuit, InvalidArgumentError, CircuitError);
+create_exception!(prepare_circuit, QasmParseError, CircuitError);
+create_exception!(prepare_circuit, PyzxJsonError, CircuitError);
+create_exception!(prepare_circuit, UnsupportedOperationError, CircuitError);
+create_exception!(prepare_circuit, SimulationError, CircuitError);
+
+impl From<Error> for PyErr {
+    fn from(error: Error) -> PyErr {
+        let message = error.to_string();
+        match error {
+            Error::InvalidArgument(_) | Error::NotControl(_) | Error::DataTable(_) => {
+                InvalidArgumentError::new_err(message)
+            }
+            Error::Parse(_) => QasmParseError::new_err(message),
+            Error::Json(_) | Error::MalformedPyzx(_) => PyzxJsonError::new_err(message),
+            Error::Measurement(_) => UnsupportedOperationError::new_err(message),
+            Error::StateVector(_) => SimulationError::new_err(message),
+        }
+    }
+}
+
+// pythonに公開する例外クラスをmoduleに登録する
+pub fn register(py: Python, m: &PyModule) -> PyResult<()> {
+    m.add("CircuitError", py.get_type::<CircuitError>())?;
+    m.add(
+        "InvalidArgumentError",
+        py.get_type::<InvalidArgumentError>(),
+    )?;
+    m.add("QasmParseError", py.get_type::<QasmParseError>())?;
+    m.add("PyzxJsonError", py.get_type::<PyzxJsonError>())?;
+    m.add(
+        "UnsupportedOperationError",
+        py.get_type::<UnsupportedOperationError>(),
+    )?;
+    m.add("SimulationError", py.get_type::<SimulationError>())?;
+    Ok(())
+}
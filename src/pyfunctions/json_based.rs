@@ -1,24 +1,58 @@
-use crate::primitive::*;
+use crate::error::{Error, Result};
 use crate::pyzx::json::*;
-use crate::pyzx::to_json::*;
-use crate::select_gates::simple_select_controls::*;
-use crate::util::*;
-
-use super::internal::uniform_layered_internal;
+use crate::qasm::from_qasm::from_qasm;
+use crate::select_gates::layered::{
+    layered_internal, parallel_map, resolve_data_table, thread_count,
+    uniform_layered_internal_resolved,
+};
+use crate::select_gates::simple_select_controls::Uncompute;
 
 use pyo3::prelude::*;
 
-#[pyfunction]
-pub fn uniform_layered(n: i32, count: i32) -> PyResult<Vec<String>> {
-    let qubits_vec = uniform_layered_internal(n, count);
-
-    let jsons = qubits_vec
-        .iter()
-        .map(|qubits| {
-            let pyzx_json = to_pyzx_circuit(qubits.clone());
-            serde_json::to_string(&pyzx_json).unwrap()
+#[pyfunction(
+    data = "None",
+    words = "None",
+    width = "None",
+    seed = "None",
+    density = "1.0",
+    length = "None",
+    threads = "None"
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn uniform_layered(
+    py: Python,
+    n: i32,
+    count: i32,
+    data: Option<Vec<Vec<bool>>>,
+    words: Option<Vec<u64>>,
+    width: Option<usize>,
+    seed: Option<u64>,
+    density: f64,
+    length: Option<usize>,
+    threads: Option<usize>,
+) -> PyResult<Vec<String>> {
+    let table = resolve_data_table(n, data, words, width)?;
+    let threads = thread_count(threads)?;
+    let jsons = py.allow_threads(|| -> Result<Vec<String>> {
+        // pyzxは測定を扱えないのでToffoliで戻す
+        let circuits = uniform_layered_internal_resolved(
+            n,
+            count,
+            length,
+            table,
+            density,
+            seed,
+            Uncompute::Toffoli,
+            threads,
+        )?;
+
+        parallel_map(&circuits, threads, |circuit| {
+            let pyzx_json = circuit.to_pyzx()?;
+            serde_json::to_string(&pyzx_json).map_err(Error::from)
         })
-        .collect::<Vec<_>>();
+        .into_iter()
+        .collect()
+    })?;
 
     Ok(jsons)
 }
@@ -26,34 +60,27 @@ pub fn uniform_layered(n: i32, count: i32) -> PyResult<Vec<String>> {
 #[pyfunction]
 #[pyo3(text_signature = "(json, /)")]
 pub fn count_t_depth(json: String) -> PyResult<i32> {
-    let pyzx: PyzxCircuitJson = serde_json::from_str(&json).unwrap();
-    let plane = pyzx.produce_plane();
+    let pyzx: PyzxCircuitJson = serde_json::from_str(&json).map_err(Error::from)?;
+    let plane = pyzx.produce_plane()?;
     let depth = PyzxCircuitJson::count_depth(&plane);
     Ok(depth)
 }
 
+/// converts an OpenQASM 2.0 circuit into pyzx json
+#[pyfunction]
+#[pyo3(text_signature = "(qasm, /)")]
+pub fn qasm_to_pyzx(qasm: String) -> PyResult<String> {
+    let circuit = from_qasm(&qasm).map_err(Error::from)?;
+    let pyzx_json = circuit.to_pyzx()?;
+    Ok(serde_json::to_string(&pyzx_json).map_err(Error::from)?)
+}
+
 #[pyfunction]
 pub fn layered(n: i32) -> PyResult<String> {
     println!();
-    let first_qubit = cellize(Qubit::new("first"));
-    let first_control = Qubit::control(first_qubit.clone());
-    let datas = (0..n)
-        .map(|i| cellize(Qubit::new(format!("data_{}", i).as_str())))
-        .collect::<Vec<_>>();
-    let ancillas = (0..n)
-        .map(|i| cellize(Qubit::new(format!("ancilla_{}", i).as_str())))
-        .collect::<Vec<_>>();
-    let controls = in_over_2n(n, &first_control, datas.clone(), ancillas.clone());
-    let target_sample_1 = cellize(Qubit::new("target_s1"));
-    let export_target_sample_1 = Qubit::export(target_sample_1.clone());
-    export_target_sample_1.control_by(&controls[0]);
-    let mut qubits = Vec::new();
-    qubits.extend(datas);
-    qubits.extend(ancillas);
-    qubits.push(first_qubit);
-    qubits.push(target_sample_1);
-
-    let pyzx_json = to_pyzx_circuit(qubits);
-    let json = serde_json::to_string(&pyzx_json).unwrap();
+    let circuit = layered_internal(n)?;
+
+    let pyzx_json = circuit.to_pyzx()?;
+    let json = serde_json::to_string(&pyzx_json).map_err(Error::from)?;
     Ok(json)
 }
@@ -1,37 +1,30 @@
 use pyo3::prelude::*;
 
-use crate::primitive::Qubit;
-use crate::qasm::to_qasm::to_qasm;
-use crate::select_gates::data_combine::combine_random_cnots_m_interaction;
+use crate::primitive::Circuit;
+use crate::select_gates::data_combine::{combine_random_cnots_m_interaction, rng_from_seed};
 use crate::select_gates::simple_select_controls::in_over_2n;
-use crate::util::cellize;
 
-// n: number of input qubits, m: interaction count
-#[pyfunction]
-pub fn uniform_layered_m_body(n: i32, m: i32) -> PyResult<String> {
-    // prepare qubits
-    let first_qubit = cellize(Qubit::new("first"));
-    let inputs = (0..n)
-        .map(|i| cellize(Qubit::new(&format!("input_{}", i))))
-        .collect::<Vec<_>>();
-    let ancillas = (0..n)
-        .map(|i| cellize(Qubit::new(&format!("ancilla_{}", i))))
-        .collect::<Vec<_>>();
-    let targets = (0..n)
-        .map(|i| cellize(Qubit::new(&format!("target_{}", i))))
-        .collect::<Vec<_>>();
+// n: number of input qubits, m: interaction count, seed: makes the interactions reproducible
+#[pyfunction(seed = "None")]
+pub fn uniform_layered_m_body(n: i32, m: i32, seed: Option<u64>) -> PyResult<String> {
+    // prepare qubits (output order: first, inputs, ancillas, targets)
+    let mut circuit = Circuit::new();
+    let first_qubit = circuit.add_qubit("first")?;
+    let inputs = circuit.register("input", n.max(0) as usize)?;
+    let ancillas = circuit.register("ancilla", n.max(0) as usize)?;
+    let targets = circuit.register("target", n.max(0) as usize)?;
     // get controls
-    let first_control_from = Qubit::control(first_qubit.clone());
-    let controls = in_over_2n(n, &first_control_from, inputs.clone(), ancillas.clone());
+    let first_control_from = circuit.control(first_qubit);
+    let controls = in_over_2n(&mut circuit, n, &first_control_from, &inputs, &ancillas)?;
     // inject data
-    let applied_targets = combine_random_cnots_m_interaction(m, controls, targets);
-    // collect qubits
-    let mut qubits = Vec::new();
-    qubits.push(first_qubit);
-    qubits.extend(inputs);
-    qubits.extend(ancillas);
-    qubits.extend(applied_targets);
+    combine_random_cnots_m_interaction(
+        &mut circuit,
+        m,
+        &controls,
+        &targets,
+        &mut rng_from_seed(seed),
+    )?;
     // get qasm string
-    let qasm_file = to_qasm(qubits);
+    let qasm_file = circuit.to_qasm();
     Ok(qasm_file.to_string())
 }
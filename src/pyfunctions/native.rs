@@ -0,0 +1,170 @@
+// 文字列を経由せず、pythonのdict/list/tupleとして直接返す
+use crate::error::{Error, Result};
+use crate::pyzx::json::PyzxCircuitJson;
+use crate::qasm::from_qasm::from_qasm;
+use crate::qasm::operations::OperationTuple;
+use crate::select_gates::layered::{
+    layered_internal, parallel_map, parse_uncompute, resolve_data_table, thread_count,
+    uniform_layered_internal_resolved,
+};
+use crate::select_gates::simple_select_controls::Uncompute;
+
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+use serde_json::Value;
+
+// serde_jsonの値をそのままpythonの値にする
+// 整数で表せる数はint、それ以外はfloatになる
+fn json_to_py(py: Python, value: &Value) -> PyResult<PyObject> {
+    Ok(match value {
+        Value::Null => py.None(),
+        Value::Bool(value) => value.to_object(py),
+        Value::Number(number) => match number.as_i64() {
+            Some(integer) => integer.to_object(py),
+            None => number.as_f64().to_object(py),
+        },
+        Value::String(value) => value.to_object(py),
+        Value::Array(values) => {
+            let list = PyList::empty(py);
+            values
+                .iter()
+                .try_for_each(|value| list.append(json_to_py(py, value)?))?;
+            list.to_object(py)
+        }
+        Value::Object(map) => {
+            let dict = PyDict::new(py);
+            map.iter()
+                .try_for_each(|(key, value)| dict.set_item(key, json_to_py(py, value)?))?;
+            dict.to_object(py)
+        }
+    })
+}
+
+// json.loads(serde_json::to_string(..))と同じ形のdictを作る
+pub fn pyzx_to_dict(py: Python, pyzx_json: &PyzxCircuitJson) -> PyResult<PyObject> {
+    let value = serde_json::to_value(pyzx_json).map_err(Error::from)?;
+    json_to_py(py, &value)
+}
+
+/// same as `uniform_layered` but returns the pyzx graphs as dicts instead of json strings
+#[pyfunction(
+    data = "None",
+    words = "None",
+    width = "None",
+    seed = "None",
+    density = "1.0",
+    length = "None",
+    threads = "None"
+)]
+#[pyo3(
+    text_signature = "(n, count, /, data=None, words=None, width=None, seed=None, density=1.0, length=None, threads=None)"
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn uniform_layered_dict(
+    py: Python,
+    n: i32,
+    count: i32,
+    data: Option<Vec<Vec<bool>>>,
+    words: Option<Vec<u64>>,
+    width: Option<usize>,
+    seed: Option<u64>,
+    density: f64,
+    length: Option<usize>,
+    threads: Option<usize>,
+) -> PyResult<Vec<PyObject>> {
+    let table = resolve_data_table(n, data, words, width)?;
+    let threads = thread_count(threads)?;
+    // dictを作るところだけGILを取る
+    let pyzx_jsons = py.allow_threads(|| -> Result<Vec<PyzxCircuitJson>> {
+        // pyzxは測定を扱えないのでToffoliで戻す
+        let circuits = uniform_layered_internal_resolved(
+            n,
+            count,
+            length,
+            table,
+            density,
+            seed,
+            Uncompute::Toffoli,
+            threads,
+        )?;
+        parallel_map(&circuits, threads, |circuit| circuit.to_pyzx())
+            .into_iter()
+            .collect()
+    })?;
+    pyzx_jsons
+        .iter()
+        .map(|pyzx_json| pyzx_to_dict(py, pyzx_json))
+        .collect()
+}
+
+/// same as `layered` but returns the pyzx graph as a dict instead of a json string
+#[pyfunction]
+#[pyo3(text_signature = "(n, /)")]
+pub fn layered_dict(py: Python, n: i32) -> PyResult<PyObject> {
+    let circuit = layered_internal(n)?;
+    pyzx_to_dict(py, &circuit.to_pyzx()?)
+}
+
+/// generates uniform layered qrom as lists of `(gate_name, qubits, params, clbits)` tuples
+///
+/// `params` are rotation angles in radians, and `clbits` holds the written bit of `measure`
+/// or the condition bit of a classically-controlled gate.
+/// The arguments are the same as `uniform_layered_qasm` without `version`.
+#[pyfunction(
+    data = "None",
+    words = "None",
+    width = "None",
+    seed = "None",
+    density = "1.0",
+    length = "None",
+    uncompute = "\"toffoli\"",
+    threads = "None"
+)]
+#[pyo3(
+    text_signature = "(n, count, /, data=None, words=None, width=None, seed=None, density=1.0, length=None, uncompute=\"toffoli\", threads=None)"
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn uniform_layered_operations(
+    py: Python,
+    n: i32,
+    count: i32,
+    data: Option<Vec<Vec<bool>>>,
+    words: Option<Vec<u64>>,
+    width: Option<usize>,
+    seed: Option<u64>,
+    density: f64,
+    length: Option<usize>,
+    uncompute: &str,
+    threads: Option<usize>,
+) -> PyResult<Vec<Vec<OperationTuple>>> {
+    let uncompute = parse_uncompute(uncompute)?;
+    let table = resolve_data_table(n, data, words, width)?;
+    let threads = thread_count(threads)?;
+    let operations = py.allow_threads(|| -> Result<Vec<Vec<OperationTuple>>> {
+        let circuits = uniform_layered_internal_resolved(
+            n, count, length, table, density, seed, uncompute, threads,
+        )?;
+        Ok(parallel_map(&circuits, threads, |circuit| {
+            circuit
+                .to_qasm()
+                .operations
+                .iter()
+                .map(|operation| operation.to_tuple())
+                .collect()
+        }))
+    })?;
+    Ok(operations)
+}
+
+/// parses an OpenQASM 2.0 circuit into a list of `(gate_name, qubits, params, clbits)` tuples
+#[pyfunction]
+#[pyo3(text_signature = "(qasm, /)")]
+pub fn qasm_operations(qasm: String) -> PyResult<Vec<OperationTuple>> {
+    let circuit = from_qasm(&qasm).map_err(Error::from)?;
+    Ok(circuit
+        .to_qasm()
+        .operations
+        .iter()
+        .map(|operation| operation.to_tuple())
+        .collect())
+}
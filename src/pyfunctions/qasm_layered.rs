@@ -1,35 +1,90 @@
-use crate::qasm::to_qasm::*;
-
-use super::internal::{uniform_layered_internal, uniform_layered_internal_redundant};
+use crate::error::{Error, Result};
+use crate::select_gates::layered::{
+    check_density, parallel_map, parse_uncompute, resolve_data_table, thread_count,
+    uniform_layered_internal_redundant, uniform_layered_internal_resolved,
+};
 
 use pyo3::prelude::*;
 
 /// generates uniform layered qrom in qasm format
-#[pyfunction]
-#[pyo3(text_signature = "(n, count, /)")]
-pub fn uniform_layered_qasm(n: i32, count: i32) -> PyResult<Vec<String>> {
-    let qubits_vec = uniform_layered_internal(n, count);
-
-    let qasms = qubits_vec
-        .iter()
-        .map(|qubits| {
-            let qasm_file = to_qasm(qubits.clone());
-            qasm_file.to_string()
-        })
-        .collect::<Vec<_>>();
+///
+/// `data` (rows of bools) or `words` (integers of `width` bits) loads a custom table.
+/// The target register has `width` qubits, which defaults to the row width of `data` or to `n` for `words`.
+/// Without a table each bit is random with probability `density`, reproducible with `seed`.
+/// `length` selects only the first `length` addresses instead of all `2^n`.
+/// `version=3` writes OpenQASM 3 with named registers instead of OpenQASM 2.0.
+/// `uncompute="measurement"` uncomputes each SELECT ancilla with an X-basis measurement
+/// and a classically-controlled CZ instead of a second Toffoli, halving the T-count.
+/// The circuits are built on `threads` worker threads (default: all cores) without the GIL.
+#[pyfunction(
+    data = "None",
+    words = "None",
+    width = "None",
+    seed = "None",
+    density = "1.0",
+    length = "None",
+    version = "2",
+    uncompute = "\"toffoli\"",
+    threads = "None"
+)]
+#[pyo3(
+    text_signature = "(n, count, /, data=None, words=None, width=None, seed=None, density=1.0, length=None, version=2, uncompute=\"toffoli\", threads=None)"
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn uniform_layered_qasm(
+    py: Python,
+    n: i32,
+    count: i32,
+    data: Option<Vec<Vec<bool>>>,
+    words: Option<Vec<u64>>,
+    width: Option<usize>,
+    seed: Option<u64>,
+    density: f64,
+    length: Option<usize>,
+    version: u8,
+    uncompute: &str,
+    threads: Option<usize>,
+) -> PyResult<Vec<String>> {
+    if version != 2 && version != 3 {
+        return Err(
+            Error::invalid_argument(format!("version must be 2 or 3, got {}", version)).into(),
+        );
+    }
+    let uncompute = parse_uncompute(uncompute)?;
+    let table = resolve_data_table(n, data, words, width)?;
+    let threads = thread_count(threads)?;
+    let qasms = py.allow_threads(|| -> Result<Vec<String>> {
+        let circuits = uniform_layered_internal_resolved(
+            n, count, length, table, density, seed, uncompute, threads,
+        )?;
+        Ok(parallel_map(&circuits, threads, |circuit| {
+            if version == 3 {
+                circuit.to_qasm3().to_string()
+            } else {
+                circuit.to_qasm().to_string()
+            }
+        }))
+    })?;
 
     Ok(qasms)
 }
 
-#[pyfunction]
-#[pyo3(text_signature = "(n, count, r, /)")]
-pub fn uniform_layered_redundant(n: i32, count: i32, r: i32) -> PyResult<Vec<String>> {
-    let qubits_vec = uniform_layered_internal_redundant(n, count, r);
+#[pyfunction(seed = "None", density = "1.0")]
+#[pyo3(text_signature = "(n, count, r, /, seed=None, density=1.0)")]
+pub fn uniform_layered_redundant(
+    n: i32,
+    count: i32,
+    r: i32,
+    seed: Option<u64>,
+    density: f64,
+) -> PyResult<Vec<String>> {
+    check_density(density)?;
+    let circuits = uniform_layered_internal_redundant(n, count, r, density, seed)?;
 
-    let qasms = qubits_vec
+    let qasms = circuits
         .iter()
-        .map(|qubits| {
-            let qasm_file = to_qasm(qubits.clone());
+        .map(|circuit| {
+            let qasm_file = circuit.to_qasm();
             qasm_file.to_string()
         })
         .collect::<Vec<_>>();
@@ -0,0 +1,33 @@
+use crate::error::Error;
+use crate::qasm::from_qasm::from_qasm;
+use crate::simulator::equivalence::equivalent_with_seed;
+use crate::simulator::statevector::simulate_file;
+
+use pyo3::prelude::*;
+use pyo3::types::PyComplex;
+
+/// simulates a qasm circuit from |0...0> and returns the amplitudes (qubit 0 is the lowest bit)
+#[pyfunction]
+#[pyo3(text_signature = "(qasm, /)")]
+pub fn simulate_qasm(py: Python<'_>, qasm: String) -> PyResult<Vec<&PyComplex>> {
+    let circuit = from_qasm(&qasm).map_err(Error::from)?;
+    let state = simulate_file(&circuit.to_qasm())?;
+    Ok(state
+        .amplitudes
+        .iter()
+        .map(|amplitude| PyComplex::from_doubles(py, amplitude.re, amplitude.im))
+        .collect())
+}
+
+/// checks whether two qasm circuits are the same unitary up to global phase
+/// qubits are matched in qreg declaration order
+#[pyfunction(seed = "None")]
+#[pyo3(text_signature = "(a, b, /, seed=None)")]
+pub fn qasm_equivalent(a: String, b: String, seed: Option<u64>) -> PyResult<bool> {
+    let parse = |qasm: &str| {
+        from_qasm(qasm)
+            .map(|circuit| circuit.to_qasm())
+            .map_err(Error::from)
+    };
+    Ok(equivalent_with_seed(&parse(&a)?, &parse(&b)?, seed)?)
+}
@@ -0,0 +1,71 @@
+use crate::error::{Error, Result};
+use crate::qasm::from_qasm::from_qasm;
+use crate::select_gates::layered::{
+    parallel_map, parse_uncompute, resolve_data_table, thread_count,
+    uniform_layered_internal_resolved,
+};
+
+use super::circuit::PyCircuit;
+
+use pyo3::prelude::*;
+use std::collections::HashMap;
+
+/// counts t_count, t_depth, cnot_count, cnot_depth, depth and qubit_count of a circuit
+///
+/// `circuit` is either a `Circuit` or an OpenQASM 2.0 string.
+#[pyfunction]
+#[pyo3(text_signature = "(circuit, /)")]
+pub fn circuit_stats(circuit: &PyAny) -> PyResult<HashMap<String, usize>> {
+    let stats = match circuit.extract::<PyRef<PyCircuit>>() {
+        Ok(circuit) => circuit.circuit.stats(),
+        Err(_) => from_qasm(circuit.extract::<&str>()?)
+            .map_err(Error::from)?
+            .stats(),
+    };
+    Ok(stats.to_map())
+}
+
+/// counts the stats of uniform layered qrom directly on the generated circuits
+///
+/// The arguments are the same as `uniform_layered_qasm` without `version`,
+/// so the counts hold for the qasm 2, qasm 3 and pyzx output alike.
+#[pyfunction(
+    data = "None",
+    words = "None",
+    width = "None",
+    seed = "None",
+    density = "1.0",
+    length = "None",
+    uncompute = "\"toffoli\"",
+    threads = "None"
+)]
+#[pyo3(
+    text_signature = "(n, count, /, data=None, words=None, width=None, seed=None, density=1.0, length=None, uncompute=\"toffoli\", threads=None)"
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn uniform_layered_stats(
+    py: Python,
+    n: i32,
+    count: i32,
+    data: Option<Vec<Vec<bool>>>,
+    words: Option<Vec<u64>>,
+    width: Option<usize>,
+    seed: Option<u64>,
+    density: f64,
+    length: Option<usize>,
+    uncompute: &str,
+    threads: Option<usize>,
+) -> PyResult<Vec<HashMap<String, usize>>> {
+    let uncompute = parse_uncompute(uncompute)?;
+    let table = resolve_data_table(n, data, words, width)?;
+    let threads = thread_count(threads)?;
+    let stats = py.allow_threads(|| -> Result<Vec<HashMap<String, usize>>> {
+        let circuits = uniform_layered_internal_resolved(
+            n, count, length, table, density, seed, uncompute, threads,
+        )?;
+        Ok(parallel_map(&circuits, threads, |circuit| {
+            circuit.stats().to_map()
+        }))
+    })?;
+    Ok(stats)
+}
@@ -1,9 +1,7 @@
+use crate::error::Error;
 use crate::gates::*;
 use crate::primitive::*;
 use crate::pyzx::json::*;
-use crate::pyzx::to_json::*;
-use crate::qasm::to_qasm::*;
-use crate::util::*;
 
 use pyo3::prelude::*;
 
@@ -15,38 +13,34 @@ pub fn sum_as_string(a: i32, b: i32) -> PyResult<String> {
 
 #[pyfunction]
 pub fn test_gate() -> PyResult<String> {
-    let q1 = cellize(Qubit::new("q1"));
-    let q2 = cellize(Qubit::new("q2"));
-    let q3 = cellize(Qubit::new("q3"));
-    // let q4 = cellize(Qubit::new("q4"));
-    // let control = Qubit::control(q1.clone());
-    // let (leftc, _) = in_layer(&control, q2.clone(), q3.clone());
-    // let leftc = in_layer(&control, q2.clone(), q3.clone());
-    // let export = Qubit::export(q4.clone());
-    // export.control_by(&leftc);
-    toffoli(q1.clone(), q2.clone(), q3.clone());
-    // let pyzx_json = to_pyzx_circuit(vec![q1.clone(), q2.clone(), q3.clone()]);
-    // let pyzx_json = to_pyzx_circuit(vec![q1.clone(), q2.clone(), q3.clone(), q4.clone()]);
-    let pyzx_json = to_pyzx_circuit(vec![q1, q2, q3]);
-    let json = serde_json::to_string(&pyzx_json).unwrap();
+    let mut circuit = Circuit::new();
+    let q1 = circuit.add_qubit("q1")?;
+    let q2 = circuit.add_qubit("q2")?;
+    let q3 = circuit.add_qubit("q3")?;
+    // let q4 = circuit.add_qubit("q4");
+    // let control = circuit.control(q1);
+    // let (leftc, _) = in_layer(&mut circuit, &control, q2, q3);
+    // let export = circuit.export(q4);
+    // export.control_by(&mut circuit, &leftc);
+    toffoli(&mut circuit, q1, q2, q3)?;
+    let pyzx_json = circuit.to_pyzx()?;
+    let json = serde_json::to_string(&pyzx_json).map_err(Error::from)?;
     Ok(json)
 }
 
 #[pyfunction]
 pub fn test_gate_qasm() -> PyResult<String> {
-    let q1 = cellize(Qubit::new("q1"));
-    let q2 = cellize(Qubit::new("q2"));
-    let q3 = cellize(Qubit::new("q3"));
-    // let q4 = cellize(Qubit::new("q4"));
-    // let control = Qubit::control(q1.clone());
-    // let (leftc, _) = in_layer(&control, q2.clone(), q3.clone());
-    // let leftc = in_layer(&control, q2.clone(), q3.clone());
-    // let export = Qubit::export(q4.clone());
-    // export.control_by(&leftc);
-    toffoli(q1.clone(), q2.clone(), q3.clone());
-    // let pyzx_json = to_pyzx_circuit(vec![q1.clone(), q2.clone(), q3.clone()]);
-    // let pyzx_json = to_pyzx_circuit(vec![q1.clone(), q2.clone(), q3.clone(), q4.clone()]);
-    let qasm_file = to_qasm(vec![q1, q2, q3]);
+    let mut circuit = Circuit::new();
+    let q1 = circuit.add_qubit("q1")?;
+    let q2 = circuit.add_qubit("q2")?;
+    let q3 = circuit.add_qubit("q3")?;
+    // let q4 = circuit.add_qubit("q4");
+    // let control = circuit.control(q1);
+    // let (leftc, _) = in_layer(&mut circuit, &control, q2, q3);
+    // let export = circuit.export(q4);
+    // export.control_by(&mut circuit, &leftc);
+    toffoli(&mut circuit, q1, q2, q3)?;
+    let qasm_file = circuit.to_qasm();
     Ok(qasm_file.to_string())
 }
 
@@ -109,6 +103,6 @@ pub fn output_json() -> PyResult<String> {
         node_vertices,
         undir_edges,
     };
-    let serialized = serde_json::to_string(&test_struct).unwrap();
+    let serialized = serde_json::to_string(&test_struct).map_err(Error::from)?;
     Ok(serialized)
 }
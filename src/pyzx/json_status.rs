@@ -2,6 +2,7 @@ use regex::Regex;
 use std::collections::HashMap;
 
 use super::json::*;
+use crate::error::{Error, Result};
 
 #[derive(Debug, Clone)]
 pub enum PlaneElement {
@@ -26,11 +27,11 @@ impl PyzxCircuitJson {
                 let from_node = graph.node_vertices.get(&from);
                 let to_node = graph.node_vertices.get(&to);
                 let from_x = match from_node {
-                    Some(from_node) => *from_node.annotation.coord.get(0).unwrap(),
+                    Some(from_node) => *from_node.annotation.coord.get(0).unwrap_or(&-1.0),
                     None => -1.0,
                 };
                 let to_x = match to_node {
-                    Some(to_node) => *to_node.annotation.coord.get(0).unwrap(),
+                    Some(to_node) => *to_node.annotation.coord.get(0).unwrap_or(&-1.0),
                     None => -1.0,
                 };
                 if from_x != to_x {
@@ -42,21 +43,31 @@ impl PyzxCircuitJson {
             .get(0).cloned();
         target
     }
-    pub fn get_node_coord(&self, node: String) -> (i32, i32) {
+    pub fn get_node_coord(&self, node: String) -> Result<(i32, i32)> {
         let graph = self;
-        let node = graph.node_vertices.get(&node).unwrap();
+        let node_data = graph
+            .node_vertices
+            .get(&node)
+            .ok_or_else(|| Error::MalformedPyzx(format!("node {} is not found", node)))?;
+        let coord = &node_data.annotation.coord;
+        if coord.len() < 2 {
+            return Err(Error::MalformedPyzx(format!(
+                "node {} does not have a 2d coord",
+                node
+            )));
+        }
         // x2しないと整数にならない
-        let coord_x = (node.annotation.coord.get(0).unwrap() * 2.0) as i32;
-        let coord_y = *node.annotation.coord.get(1).unwrap() as i32;
-        (coord_x, coord_y)
+        let coord_x = (coord[0] * 2.0) as i32;
+        let coord_y = coord[1] as i32;
+        Ok((coord_x, coord_y))
     }
-    pub fn produce_plane(&self) -> HashMap<i32, HashMap<i32, PlaneElement>> {
+    pub fn produce_plane(&self) -> Result<HashMap<i32, HashMap<i32, PlaneElement>>> {
         let graph = self;
         let nodes = graph.node_vertices.clone();
         // 横方向はx2して整数的にしてから入れる
         let mut map: HashMap<i32, HashMap<i32, PlaneElement>> = HashMap::new();
         for (node, node_data) in nodes {
-            let coord = self.get_node_coord(node.clone());
+            let coord = self.get_node_coord(node.clone())?;
             // 行に当たるもの
             let new_row = HashMap::new();
             let row = match map.get_mut(&coord.1) {
@@ -68,7 +79,10 @@ impl PyzxCircuitJson {
             };
             // PlaneElementを錬成する
             let cross_target_node = self.find_vertical_node(node.clone());
-            let cross_target_y = cross_target_node.map(|s| self.get_node_coord(s).1);
+            let cross_target_y = match cross_target_node {
+                Some(s) => Some(self.get_node_coord(s)?.1),
+                None => None,
+            };
             let node_value = node_data.data.value.clone();
             let is_t_value = match node_value {
                 Some(str) => {
@@ -82,16 +96,27 @@ impl PyzxCircuitJson {
                 (true, None) => PlaneElement::T,
                 (false, Some(y)) => PlaneElement::Cross(y),
                 (false, None) => PlaneElement::Ordinal,
-                (true, Some(_)) => panic!("T value cannot have vertical cross"),
+                (true, Some(_)) => {
+                    return Err(Error::MalformedPyzx(format!(
+                        "T value cannot have vertical cross at {}",
+                        node
+                    )))
+                }
             };
             row.insert(coord.0, plane_elem);
         }
-        map
+        Ok(map)
     }
     pub fn count_depth(plane: &HashMap<i32, HashMap<i32, PlaneElement>>) -> i32 {
         let mut count_map: HashMap<i32, HashMap<i32, i32>> = HashMap::new();
         let row_count = plane.len();
-        let column_count = plane.iter().map(|(_, row)| row.keys().max().unwrap()).max().unwrap() * 2;
+        // 空の回路はdepth 0
+        let column_count = plane
+            .iter()
+            .filter_map(|(_, row)| row.keys().max())
+            .max()
+            .unwrap_or(&0)
+            * 2;
         for x in (0..column_count).map(|x| x as i32) {
             for y in (0..row_count).map(|y| -(y as i32)) {
                 // left countを計算する
@@ -158,14 +183,14 @@ impl PyzxCircuitJson {
         
         (0..row_count)
             .map(|y| -(y as i32))
-            .map(|y| {
+            .filter_map(|y| {
                 let y = y as i32;
-                let row = count_map.get(&y).unwrap();
-                let last_value = row.get(&((column_count as i32) - 1)).unwrap();
-                *last_value
+                let row = count_map.get(&y)?;
+                let last_value = row.get(&((column_count as i32) - 1))?;
+                Some(*last_value)
             })
             .max()
-            .unwrap()
+            .unwrap_or(0)
     }
 }
 
@@ -183,9 +208,17 @@ mod tests {
     fn real_world_test() {
         let json = &sample_json("./test/depth-20.json");
         let pyzx = serde_json::from_str::<PyzxCircuitJson>(json).unwrap();
-        let plane = pyzx.produce_plane();
+        let plane = pyzx.produce_plane().unwrap();
         let depth = PyzxCircuitJson::count_depth(&plane);
         println!("{:#?}", depth);
         assert_eq!(depth, 20)
     }
+    #[test]
+    fn malformed_test() {
+        let json = r#"{"wire_vertices": {}, "node_vertices": {"v0": {"annotation": {"coord": [1.0]}, "data": {"type": "Z"}}}, "undir_edges": {}}"#;
+        let pyzx = serde_json::from_str::<PyzxCircuitJson>(json).unwrap();
+        assert!(matches!(pyzx.produce_plane(), Err(Error::MalformedPyzx(_))));
+        let empty = HashMap::new();
+        assert_eq!(PyzxCircuitJson::count_depth(&empty), 0);
+    }
 }
@@ -1,7 +1,7 @@
 use super::json;
-use crate::primitive::{self, OperationCell};
+use crate::primitive::{self, OpId, OperationRef, QubitRef};
 
-pub trait QubitSerializeUtil {
+pub trait QubitSerializeUtil<'a> {
     fn operation_count(&self) -> usize;
     fn raw_operation_count(&self) -> usize;
     fn qubit_id(&self) -> String;
@@ -11,12 +11,12 @@ pub trait QubitSerializeUtil {
     fn output_qubit_id(&self) -> String {
         format!("output_{}", self.qubit_id())
     }
-    fn get_operation(&self, index: usize) -> Option<primitive::OperationCell>;
-    fn iter(&self) -> QubitOperationsIter;
-    fn get_first(&self) -> Option<primitive::OperationCell> {
+    fn get_operation(&self, index: usize) -> Option<OperationRef<'a>>;
+    fn iter(&self) -> QubitOperationsIter<'a>;
+    fn get_first(&self) -> Option<OperationRef<'a>> {
         self.get_operation(0)
     }
-    fn get_last(&self) -> Option<primitive::OperationCell> {
+    fn get_last(&self) -> Option<OperationRef<'a>> {
         let target = self.raw_operation_count() as i32 - 1;
         if target < 0 {
             return None;
@@ -25,14 +25,12 @@ pub trait QubitSerializeUtil {
     }
 }
 
-impl QubitSerializeUtil for primitive::QubitCell {
+impl<'a> QubitSerializeUtil<'a> for QubitRef<'a> {
     fn operation_count(&self) -> usize {
-        self.as_ref()
-            .borrow()
-            .operations
+        self.operations()
             .iter()
             .map(|op| {
-                let node = &op.as_ref().borrow().node_type;
+                let node = &self.circuit.operation(*op).node_type;
                 match node {
                     primitive::NodeType::ControlledNot(_, count) => count.clone() as usize,
                     _ => 1 as usize,
@@ -41,36 +39,31 @@ impl QubitSerializeUtil for primitive::QubitCell {
             .sum::<usize>()
     }
     fn raw_operation_count(&self) -> usize {
-        self.as_ref().borrow().operations.len()
+        self.operations().len()
     }
     fn qubit_id(&self) -> String {
-        self.as_ref().borrow().id.clone()
+        self.name().to_string()
     }
-    fn get_operation(&self, index: usize) -> Option<primitive::OperationCell> {
-        self.as_ref()
-            .borrow()
-            .operations
-            .get(index)
-            .map(|c| c.clone())
+    fn get_operation(&self, index: usize) -> Option<OperationRef<'a>> {
+        self.operation(index)
     }
-    fn iter(&self) -> QubitOperationsIter {
+    fn iter(&self) -> QubitOperationsIter<'a> {
         QubitOperationsIter {
             index: 0,
-            qcell: self.clone(),
+            qcell: *self,
             cnot_index: 0,
         }
     }
 }
 
-pub trait OperationSerializeUtil {
+pub trait OperationSerializeUtil<'a> {
     // アクセサ系
-    fn parent(&self) -> primitive::QubitCell;
     fn parent_id(&self) -> String;
     fn is_control(&self) -> bool;
     fn is_controlled(&self) -> bool;
     fn control_count(&self) -> i32;
     fn control_position(&self) -> i32;
-    fn control_from_op(&self) -> Option<primitive::OperationCell>;
+    fn control_from_op(&self) -> Option<OperationRef<'a>>;
     // controlの時はposも考慮する
     fn get_node_id(&self, pos: Option<i32>) -> String;
     fn get_control_from_node_id(&self) -> String;
@@ -79,15 +72,12 @@ pub trait OperationSerializeUtil {
     fn create_nodes(&self, start_coord: json::Coord) -> Vec<(String, json::NodeVerticesValue)>;
 }
 
-impl OperationSerializeUtil for primitive::OperationCell {
-    fn parent(&self) -> primitive::QubitCell {
-        self.as_ref().borrow().parent.upgrade().unwrap().clone()
-    }
+impl<'a> OperationSerializeUtil<'a> for OperationRef<'a> {
     fn parent_id(&self) -> String {
         self.parent().qubit_id()
     }
     fn is_control(&self) -> bool {
-        let node_type = &self.as_ref().borrow().node_type;
+        let node_type = self.node_type();
         if let primitive::NodeType::Control(_) = node_type {
             return true;
         } else {
@@ -95,7 +85,7 @@ impl OperationSerializeUtil for primitive::OperationCell {
         }
     }
     fn is_controlled(&self) -> bool {
-        let node_type = &self.as_ref().borrow().node_type;
+        let node_type = self.node_type();
         if let primitive::NodeType::ControlledNot(_, _) = node_type {
             return true;
         } else {
@@ -103,7 +93,7 @@ impl OperationSerializeUtil for primitive::OperationCell {
         }
     }
     fn control_count(&self) -> i32 {
-        let node_type = &self.as_ref().borrow().node_type;
+        let node_type = self.node_type();
         if let primitive::NodeType::Control(count) = node_type {
             return count.clone();
         } else {
@@ -111,25 +101,27 @@ impl OperationSerializeUtil for primitive::OperationCell {
         }
     }
     fn control_position(&self) -> i32 {
-        let node_type = &self.as_ref().borrow().node_type;
+        let node_type = self.node_type();
         if let primitive::NodeType::ControlledNot(_, position) = node_type {
             return position.clone();
         } else {
             return 0;
         }
     }
-    fn control_from_op(&self) -> Option<primitive::OperationCell> {
-        let node_type = &self.as_ref().borrow().node_type;
+    fn control_from_op(&self) -> Option<OperationRef<'a>> {
+        let node_type = self.node_type();
         if let primitive::NodeType::ControlledNot(op, _) = node_type {
-            let upgraded = op.upgrade().unwrap();
-            return Some(upgraded);
+            return Some(OperationRef {
+                circuit: self.circuit,
+                id: *op,
+            });
         } else {
             return None;
         }
     }
     fn get_node_id(&self, pos: Option<i32>) -> String {
         let parent = self.parent_id();
-        let operation_id = self.as_ref().borrow().id.clone();
+        let operation_id = self.operation().id;
         if let Some(pos) = pos {
             return format!("qnode_{}_op{}_pos{}", parent, operation_id, pos);
         } else {
@@ -160,9 +152,15 @@ impl OperationSerializeUtil for primitive::OperationCell {
             let node_annotation = json::NodeVerticesAnnotation {
                 coord: coord.clone(),
             };
-            let node_type = &self.as_ref().borrow().node_type;
+            let node_type = self.node_type();
             let node_value = match node_type {
                 primitive::NodeType::Control(_) => panic!("unreachable condition"),
+                // PyZXの回路は測定や古典bitを持たない
+                primitive::NodeType::Measure(_)
+                | primitive::NodeType::Reset
+                | primitive::NodeType::Conditioned(_, _) => {
+                    panic!("measurement cannot be converted to pyzx")
+                }
                 primitive::NodeType::ControlledNot(_, _) => json::NodeVerticesData {
                     kind: "X".to_string(),
                     value: None,
@@ -209,6 +207,20 @@ impl OperationSerializeUtil for primitive::OperationCell {
                         value: None,
                         is_edge: Some("true".to_string()),
                     },
+                    primitive::PrimitiveGate::Rz(angle) => json::NodeVerticesData {
+                        kind: "Z".to_string(),
+                        value: angle.to_pyzx_string(),
+                        is_edge: None,
+                    },
+                    primitive::PrimitiveGate::Rx(angle) => json::NodeVerticesData {
+                        kind: "X".to_string(),
+                        value: angle.to_pyzx_string(),
+                        is_edge: None,
+                    },
+                    // to_jsonでRz/Rxに分解してから渡す
+                    primitive::PrimitiveGate::Ry(_) | primitive::PrimitiveGate::U(_, _, _) => {
+                        panic!("unreachable condition")
+                    }
                 },
             };
             // 場合分けしていく
@@ -237,14 +249,14 @@ impl OperationSerializeUtil for primitive::OperationCell {
 }
 
 #[derive(Clone, Debug)]
-pub struct QubitOperationsIter {
-    qcell: primitive::QubitCell,
+pub struct QubitOperationsIter<'a> {
+    qcell: QubitRef<'a>,
     // nextに使うためのindex
     index: usize,
     cnot_index: usize,
 }
-impl QubitOperationsIter {
-    pub fn get_item(&self) -> Option<QubitOperationsIterItem> {
+impl<'a> QubitOperationsIter<'a> {
+    pub fn get_item(&self) -> Option<QubitOperationsIterItem<'a>> {
         if self.index >= self.qcell.raw_operation_count() {
             return None;
         }
@@ -278,13 +290,13 @@ impl QubitOperationsIter {
     }
 }
 #[derive(Clone, Debug)]
-pub struct QubitOperationsIterItem {
-    pub previous: Option<primitive::OperationCell>,
-    pub current: primitive::OperationCell,
+pub struct QubitOperationsIterItem<'a> {
+    pub previous: Option<OperationRef<'a>>,
+    pub current: OperationRef<'a>,
     pub cnot_pos: usize,
 }
-impl Iterator for QubitOperationsIter {
-    type Item = QubitOperationsIterItem;
+impl<'a> Iterator for QubitOperationsIter<'a> {
+    type Item = QubitOperationsIterItem<'a>;
     // cnotの場合、そのcnotの何番目かは返されないが、positionでそれは頑張る
     fn next(&mut self) -> Option<Self::Item> {
         let item = self.get_item();
@@ -314,32 +326,34 @@ impl Iterator for QubitOperationsIter {
         } else {
             1
         };
-        self.cnot_index = if current.is_control() {
-            self.cnot_index + 1
-        } else {
-            0
-        };
+        // controlを抜けて次の操作に進んだらcnotの位置は0から数え直す
+        self.cnot_index =
+            if current.is_control() && (self.cnot_index as i32) < current.control_count() - 1 {
+                self.cnot_index + 1
+            } else {
+                0
+            };
 
         return item;
     }
 }
 
 #[derive(Clone, Debug)]
-pub struct QubitsIter {
-    qiters: Vec<QubitOperationsIter>,
+pub struct QubitsIter<'a> {
+    qiters: Vec<QubitOperationsIter<'a>>,
 }
 #[derive(Clone, Debug)]
-pub struct QubitsIterItem {
-    pub items: Vec<Option<<QubitOperationsIter as Iterator>::Item>>,
+pub struct QubitsIterItem<'a> {
+    pub items: Vec<Option<<QubitOperationsIter<'a> as Iterator>::Item>>,
 }
-impl QubitsIter {
-    pub fn new(qcells: Vec<primitive::QubitCell>) -> Self {
-        let qiters = qcells.iter().map(|qcell| qcell.clone().iter()).collect();
+impl<'a> QubitsIter<'a> {
+    pub fn new(qcells: Vec<QubitRef<'a>>) -> Self {
+        let qiters = qcells.iter().map(|qcell| qcell.iter()).collect();
         QubitsIter { qiters }
     }
 }
-impl Iterator for QubitsIter {
-    type Item = QubitsIterItem;
+impl<'a> Iterator for QubitsIter<'a> {
+    type Item = QubitsIterItem<'a>;
     #[allow(clippy::if_same_then_else)]
     fn next(&mut self) -> Option<Self::Item> {
         // currentのものからまず出力できるものを作る
@@ -372,7 +386,7 @@ impl Iterator for QubitsIter {
                 .iter()
                 .filter_map(|index| {
                     let get_cnot_pos = |index: &usize| {
-                        let cnot_op = current_list[*index].as_ref().unwrap().clone();
+                        let cnot_op = current_list[*index].unwrap();
 
                         let cnot_pos = if cnot_op.is_controlled() {
                             cnot_op.control_position()
@@ -381,11 +395,12 @@ impl Iterator for QubitsIter {
                         };
                         return (cnot_op, cnot_pos);
                     };
-                    fn control_from_qubit_id(opc: OperationCell) -> Option<String> {
+                    // 同じqubitに別のcontrolが続くこともあるので、qubitではなくcontrolの操作で対応を取る
+                    fn control_from_op_id(opc: OperationRef<'_>) -> Option<OpId> {
                         if opc.is_control() {
-                            return Some(opc.parent_id());
+                            return Some(opc.id);
                         } else if opc.is_controlled() {
-                            return Some(opc.control_from_op().unwrap().parent_id());
+                            return Some(opc.control_from_op().unwrap().id);
                         } else {
                             return None;
                         }
@@ -399,8 +414,8 @@ impl Iterator for QubitsIter {
                         } else {
                             let (temp_cnot_op, temp_cnot_pos) = get_cnot_pos(idx);
                             if temp_cnot_pos == cnot_pos {
-                                let temp_from_id = control_from_qubit_id(temp_cnot_op);
-                                let from_id = control_from_qubit_id(cnot_op.clone());
+                                let temp_from_id = control_from_op_id(temp_cnot_op);
+                                let from_id = control_from_op_id(cnot_op);
                                 temp_from_id == from_id
                             } else {
                                 false
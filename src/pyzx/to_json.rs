@@ -1,8 +1,50 @@
 use super::json;
 use super::serialize_utils::*;
+use crate::error::{Error, Result};
 use crate::primitive;
 
-pub fn to_pyzx_graph(qubit_cells: Vec<primitive::QubitCell>) -> json::PyzxCircuitJson {
+// PyZXの回路は測定を持てない
+fn check_convertible(circuit: &primitive::Circuit) -> Result<()> {
+    let has_measurement = circuit.operations.iter().any(|operation| {
+        matches!(
+            operation.node_type,
+            primitive::NodeType::Measure(_)
+                | primitive::NodeType::Reset
+                | primitive::NodeType::Conditioned(_, _)
+        )
+    });
+    if has_measurement {
+        Err(Error::Measurement("converted to pyzx"))
+    } else {
+        Ok(())
+    }
+}
+
+// PyZXのspiderはZ軸とX軸の回転しか持たないので、Ry/UはRz/Rxに分解しておく
+fn expand_rotations(circuit: &primitive::Circuit) -> primitive::Circuit {
+    use primitive::{Angle, PrimitiveGate};
+    let quarter = Angle::PiRatio(1, 2);
+    circuit.map_gates(|gate| match gate {
+        // Ry(θ) = Rz(π/2)Rx(θ)Rz(-π/2)
+        PrimitiveGate::Ry(theta) => vec![
+            PrimitiveGate::Rz(-quarter),
+            PrimitiveGate::Rx(theta),
+            PrimitiveGate::Rz(quarter),
+        ],
+        // U(θ, φ, λ) = Rz(φ + π/2)Rx(θ)Rz(λ - π/2)
+        PrimitiveGate::U(theta, phi, lambda) => vec![
+            PrimitiveGate::Rz(lambda + -quarter),
+            PrimitiveGate::Rx(theta),
+            PrimitiveGate::Rz(phi + quarter),
+        ],
+        gate => vec![gate],
+    })
+}
+
+pub fn to_pyzx_graph(circuit: &primitive::Circuit) -> Result<json::PyzxCircuitJson> {
+    check_convertible(circuit)?;
+    let circuit = &expand_rotations(circuit);
+    let qubit_cells = circuit.qubit_refs();
     let mut wire_vertices = json::WireVertices::new();
     let mut node_vertices = json::NodeVertices::new();
     let mut undir_edges = json::UndirEdges::new();
@@ -10,7 +52,7 @@ pub fn to_pyzx_graph(qubit_cells: Vec<primitive::QubitCell>) -> json::PyzxCircui
         .iter()
         .map(|q| q.operation_count())
         .max()
-        .unwrap()
+        .unwrap_or(0)
         + 1) as f64;
     qubit_cells.iter().enumerate().for_each(|(i, q)| {
         let y_coord = -(i as f64);
@@ -25,7 +67,9 @@ pub fn to_pyzx_graph(qubit_cells: Vec<primitive::QubitCell>) -> json::PyzxCircui
         );
         // node_verticesを入れてx方向のundir_edgesを作る
         // controlled_notがあったら適宜y方向のundir_edgeを作る
-        let operations = &q.as_ref().borrow().operations;
+        let operations = (0..q.raw_operation_count())
+            .filter_map(|index| q.get_operation(index))
+            .collect::<Vec<_>>();
         let mut previous_node_id = q.input_qubit_id();
         let mut start_x_coord = 1.0;
         operations.iter().for_each(|op| {
@@ -89,14 +133,18 @@ pub fn to_pyzx_graph(qubit_cells: Vec<primitive::QubitCell>) -> json::PyzxCircui
             },
         );
     });
-    json::PyzxCircuitJson {
+    Ok(json::PyzxCircuitJson {
         wire_vertices,
         node_vertices,
         undir_edges,
-    }
+    })
 }
 
-pub fn to_pyzx_circuit(qubit_cells: Vec<primitive::QubitCell>) -> json::PyzxCircuitJson {
+// qubitはcircuitに追加した順に上から並ぶ
+pub fn to_pyzx_circuit(circuit: &primitive::Circuit) -> Result<json::PyzxCircuitJson> {
+    check_convertible(circuit)?;
+    let circuit = &expand_rotations(circuit);
+    let qubit_cells = circuit.qubit_refs();
     let mut wire_vertices = json::WireVertices::new();
     let mut node_vertices = json::NodeVertices::new();
     let mut undir_edges = json::UndirEdges::new();
@@ -150,7 +198,7 @@ pub fn to_pyzx_circuit(qubit_cells: Vec<primitive::QubitCell>) -> json::PyzxCirc
                     if previous.is_some() {
                         if *cnot_pos == 0 {
                             // 前のはcnotではない
-                            let previous = previous.clone().unwrap();
+                            let previous = previous.unwrap();
                             let previous_node_id = previous.get_node_id(if previous.is_control() {
                                 let count = previous.control_count();
                                 if count == 0 {
@@ -230,16 +278,16 @@ pub fn to_pyzx_circuit(qubit_cells: Vec<primitive::QubitCell>) -> json::PyzxCirc
             );
         }
     });
-    json::PyzxCircuitJson {
+    Ok(json::PyzxCircuitJson {
         wire_vertices,
         node_vertices,
         undir_edges,
-    }
+    })
 }
 #[cfg(test)]
 mod tests {
+    use crate::gates::toffoli;
     use crate::select_gates::simple_select_controls::*;
-    use crate::{gates::toffoli, util::cellize};
 
     use super::*;
     #[test]
@@ -306,46 +354,100 @@ mod tests {
     }
     #[test]
     fn test_graph() {
-        let qcell = cellize(primitive::Qubit::new("q1"));
-        primitive::Qubit::gate(qcell.clone(), primitive::PrimitiveGate::H);
-        let pyzx_json = to_pyzx_graph(vec![qcell.clone()]);
+        let mut circuit = primitive::Circuit::new();
+        let qcell = circuit.add_qubit("q1").unwrap();
+        circuit.gate(qcell, primitive::PrimitiveGate::H);
+        let pyzx_json = to_pyzx_graph(&circuit).unwrap();
         println!("{:?}", pyzx_json);
     }
     #[test]
     fn test_circuit() {
-        let q1 = cellize(primitive::Qubit::new("q1"));
-        let q2 = cellize(primitive::Qubit::new("q2"));
-        let q3 = cellize(primitive::Qubit::new("q3"));
-        toffoli(q1.clone(), q2.clone(), q3.clone());
-        let pyzx_json = to_pyzx_circuit(vec![q1, q2, q3]);
+        let mut circuit = primitive::Circuit::new();
+        let q1 = circuit.add_qubit("q1").unwrap();
+        let q2 = circuit.add_qubit("q2").unwrap();
+        let q3 = circuit.add_qubit("q3").unwrap();
+        toffoli(&mut circuit, q1, q2, q3).unwrap();
+        let pyzx_json = to_pyzx_circuit(&circuit).unwrap();
         println!("{:?}", pyzx_json);
     }
     #[test]
+    fn test_rotations() {
+        let mut circuit = primitive::Circuit::new();
+        let q1 = circuit.add_qubit("q1").unwrap();
+        let angle = primitive::Angle::pi_ratio(1, 4).unwrap();
+        circuit.gate(q1, primitive::PrimitiveGate::Ry(angle));
+        let pyzx_json = to_pyzx_circuit(&circuit).unwrap();
+        // Ryは3つのspiderに分解される
+        let mut nodes = pyzx_json
+            .node_vertices
+            .values()
+            .map(|node| (node.data.kind.as_str(), node.data.value.as_deref()))
+            .collect::<Vec<_>>();
+        nodes.sort();
+        assert_eq!(
+            nodes,
+            vec![
+                ("X", Some("\\pi/4")),
+                ("Z", Some("-\\pi/2")),
+                ("Z", Some("\\pi/2"))
+            ]
+        );
+    }
+    #[test]
+    fn consecutive_controls() {
+        // 同じqubitから別々のcontrolが続くと、以前はiterが止まらなかった
+        let mut circuit = primitive::Circuit::new();
+        let q1 = circuit.add_qubit("q1").unwrap();
+        let q2 = circuit.add_qubit("q2").unwrap();
+        let q3 = circuit.add_qubit("q3").unwrap();
+        crate::gates::cnot(&mut circuit, q1, q2).unwrap();
+        crate::gates::cnot(&mut circuit, q1, q3).unwrap();
+        crate::gates::cnot(&mut circuit, q3, q2).unwrap();
+        let pyzx_json = to_pyzx_circuit(&circuit).unwrap();
+        assert_eq!(pyzx_json.node_vertices.len(), 6);
+        // 各qubitの線(3+3+3)とcnotの線(3)
+        assert_eq!(pyzx_json.undir_edges.len(), 12);
+        let plane = pyzx_json.produce_plane().unwrap();
+        assert_eq!(json::PyzxCircuitJson::count_depth(&plane), 0);
+    }
+    #[test]
+    fn rejects_measurement() {
+        let mut circuit = primitive::Circuit::new();
+        let q1 = circuit.add_qubit("q1").unwrap();
+        let c = circuit.add_clbit("c");
+        circuit.measure(q1, c);
+        assert!(matches!(
+            to_pyzx_circuit(&circuit),
+            Err(Error::Measurement(_))
+        ));
+    }
+    #[test]
     fn test_incomplete() {
-        let q1 = cellize(primitive::Qubit::new("q1"));
-        let q2 = cellize(primitive::Qubit::new("q2"));
-        primitive::Qubit::control(q2.clone());
-        let from = primitive::Qubit::control(q1.clone());
-        let target = primitive::Qubit::export(q2.clone());
-        target.control_by(&from);
-        let pyzx_json = to_pyzx_circuit(vec![q1, q2]);
+        let mut circuit = primitive::Circuit::new();
+        let q1 = circuit.add_qubit("q1").unwrap();
+        let q2 = circuit.add_qubit("q2").unwrap();
+        circuit.control(q2);
+        let from = circuit.control(q1);
+        let target = circuit.export(q2);
+        target.control_by(&mut circuit, &from).unwrap();
+        let pyzx_json = to_pyzx_circuit(&circuit).unwrap();
         println!("{:?}", pyzx_json);
     }
     #[test]
     fn test_middle_complete() {
-        let q1 = cellize(primitive::Qubit::new("q1"));
-        let q2 = cellize(primitive::Qubit::new("q2"));
-        let q3 = cellize(primitive::Qubit::new("q3"));
-        let q4 = cellize(primitive::Qubit::new("q4"));
-        let control = primitive::Qubit::control(q1.clone());
-        let (leftc, _) = in_layer(&control, q2.clone(), q3.clone());
-        // let leftc = in_layer(&control, q2.clone(), q3.clone());
-        let export = primitive::Qubit::export(q4.clone());
-        export.control_by(&leftc);
-        // toffoli_first_control(&control, q2.clone(), q3.clone());
-        let qcells = vec![q1.clone(), q2.clone(), q3.clone(), q4.clone()];
-        println!("{:?}", qcells);
-        let pyzx_json = to_pyzx_circuit(qcells);
+        let mut circuit = primitive::Circuit::new();
+        let q1 = circuit.add_qubit("q1").unwrap();
+        let q2 = circuit.add_qubit("q2").unwrap();
+        let q3 = circuit.add_qubit("q3").unwrap();
+        let q4 = circuit.add_qubit("q4").unwrap();
+        let control = circuit.control(q1);
+        let (leftc, _) = in_layer(&mut circuit, &control, q2, q3).unwrap();
+        // let leftc = in_layer(&mut circuit, &control, q2, q3).unwrap();
+        let export = circuit.export(q4);
+        export.control_by(&mut circuit, &leftc).unwrap();
+        // toffoli_first_control(&mut circuit, &control, q2, q3).unwrap();
+        println!("{:?}", circuit);
+        let pyzx_json = to_pyzx_circuit(&circuit).unwrap();
         println!("{:?}", pyzx_json);
     }
 }
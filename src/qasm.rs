@@ -1,3 +1,5 @@
+pub mod from_qasm;
 pub mod operations;
 pub mod serialize_utils;
 pub mod to_qasm;
+pub mod to_qasm3;
@@ -0,0 +1,946 @@
+// OpenQASM 2.0の文字列をprimitiveの回路に読み込む
+use crate::gates::{ccx, cnot};
+use crate::primitive::{reduce, Angle, Circuit, ClbitId, PrimitiveGate, QubitId};
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::fmt;
+
+// どこで失敗したかを持つエラー
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(position: Position, message: impl Into<String>) -> Self {
+        ParseError {
+            line: position.line,
+            column: position.column,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Position {
+    line: usize,
+    column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(String),
+    Str(String),
+    Symbol(char),
+}
+
+// 1文字ずつ見て位置付きのtokenに分ける
+fn tokenize(source: &str) -> Result<Vec<(Token, Position)>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    let mut position = Position { line: 1, column: 1 };
+    let advance = |c: char, position: &mut Position| {
+        if c == '\n' {
+            position.line += 1;
+            position.column = 1;
+        } else {
+            position.column += 1;
+        }
+    };
+    while let Some(&c) = chars.peek() {
+        let start = position;
+        if c.is_whitespace() {
+            chars.next();
+            advance(c, &mut position);
+        } else if c == '/' {
+            chars.next();
+            advance(c, &mut position);
+            if chars.peek() != Some(&'/') {
+                tokens.push((Token::Symbol(c), start));
+                continue;
+            }
+            // 行末までコメント
+            while let Some(&c) = chars.peek() {
+                if c == '\n' {
+                    break;
+                }
+                chars.next();
+                advance(c, &mut position);
+            }
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut ident = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_') {
+                    break;
+                }
+                ident.push(c);
+                chars.next();
+                advance(c, &mut position);
+            }
+            tokens.push((Token::Ident(ident), start));
+        } else if c.is_ascii_digit() {
+            // 1e-05のような指数部も1つの数として読む
+            let mut number = String::new();
+            let mut exponent = false;
+            while let Some(&c) = chars.peek() {
+                let after_e = number.ends_with(['e', 'E']);
+                let accept = c.is_ascii_digit()
+                    || (c == '.' && !exponent)
+                    || ((c == 'e' || c == 'E') && !exponent)
+                    || ((c == '+' || c == '-') && after_e);
+                if !accept {
+                    break;
+                }
+                exponent |= c == 'e' || c == 'E';
+                number.push(c);
+                chars.next();
+                advance(c, &mut position);
+            }
+            if !number.ends_with(|c: char| c.is_ascii_digit() || c == '.') {
+                return Err(ParseError::new(
+                    start,
+                    format!("malformed exponent in {}", number),
+                ));
+            }
+            tokens.push((Token::Number(number), start));
+        } else if c == '"' {
+            chars.next();
+            advance(c, &mut position);
+            let mut string = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => {
+                        advance('"', &mut position);
+                        break;
+                    }
+                    Some(c) => {
+                        string.push(c);
+                        advance(c, &mut position);
+                    }
+                    None => return Err(ParseError::new(start, "unterminated string")),
+                }
+            }
+            tokens.push((Token::Str(string), start));
+        } else if ";,[]()*+-=>".contains(c) {
+            chars.next();
+            advance(c, &mut position);
+            tokens.push((Token::Symbol(c), start));
+        } else {
+            return Err(ParseError::new(
+                start,
+                format!("unexpected character '{}'", c),
+            ));
+        }
+    }
+    Ok(tokens)
+}
+
+// 引数はregister全体か、indexつきの1 qubit
+#[derive(Debug, Clone)]
+enum Argument {
+    Register(String),
+    Indexed(String, usize),
+}
+
+// パラメータの式の値
+// πの0次か1次の有理数倍である間は分数のまま計算する
+#[derive(Debug, Clone, Copy)]
+enum Value {
+    // numerator / denominator * (piならπ)
+    Exact {
+        numerator: i64,
+        denominator: i64,
+        pi: bool,
+    },
+    Float(f64),
+}
+
+impl Value {
+    fn exact(numerator: Option<i64>, denominator: Option<i64>, pi: bool) -> Option<Value> {
+        let (numerator, denominator) = reduce(numerator?, denominator?)?;
+        Some(Value::Exact {
+            numerator,
+            denominator,
+            pi,
+        })
+    }
+    fn to_f64(self) -> f64 {
+        match self {
+            Value::Exact {
+                numerator,
+                denominator,
+                pi,
+            } => {
+                let value = numerator as f64 / denominator as f64;
+                if pi {
+                    value * PI
+                } else {
+                    value
+                }
+            }
+            Value::Float(value) => value,
+        }
+    }
+    fn to_angle(self) -> Angle {
+        match self {
+            Value::Exact {
+                numerator,
+                denominator,
+                pi,
+            } if pi || numerator == 0 => Angle::PiRatio(numerator, denominator),
+            value => Angle::Radians(value.to_f64()),
+        }
+    }
+    // i64::MINは符号を反転できないので浮動小数点数にする
+    fn neg(self) -> Value {
+        match self {
+            Value::Exact {
+                numerator,
+                denominator,
+                pi,
+            } => match numerator.checked_neg() {
+                Some(numerator) => Value::Exact {
+                    numerator,
+                    denominator,
+                    pi,
+                },
+                None => Value::Float(-self.to_f64()),
+            },
+            Value::Float(value) => Value::Float(-value),
+        }
+    }
+    // 分数のままで計算できない場合(πの2乗や桁あふれ)は浮動小数点数にする
+    fn apply(self, operator: char, other: Value) -> Value {
+        let exact = match (self, other) {
+            (
+                Value::Exact {
+                    numerator: a,
+                    denominator: b,
+                    pi: pi_a,
+                },
+                Value::Exact {
+                    numerator: c,
+                    denominator: d,
+                    pi: pi_b,
+                },
+            ) => match operator {
+                '+' | '-' if pi_a == pi_b => {
+                    let c = if operator == '-' {
+                        c.checked_neg()
+                    } else {
+                        Some(c)
+                    };
+                    let numerator = a
+                        .checked_mul(d)
+                        .zip(c.and_then(|c| c.checked_mul(b)))
+                        .and_then(|(ad, cb)| ad.checked_add(cb));
+                    Value::exact(numerator, b.checked_mul(d), pi_a)
+                }
+                '*' if !(pi_a && pi_b) => {
+                    Value::exact(a.checked_mul(c), b.checked_mul(d), pi_a || pi_b)
+                }
+                '/' if c != 0 && (pi_a || !pi_b) => {
+                    Value::exact(a.checked_mul(d), b.checked_mul(c), pi_a && !pi_b)
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        exact.unwrap_or_else(|| {
+            let (a, b) = (self.to_f64(), other.to_f64());
+            Value::Float(match operator {
+                '+' => a + b,
+                '-' => a - b,
+                '*' => a * b,
+                _ => a / b,
+            })
+        })
+    }
+}
+
+struct Parser {
+    tokens: Vec<(Token, Position)>,
+    index: usize,
+    end: Position,
+    // register名 -> (宣言順のqubit)
+    registers: HashMap<String, Vec<QubitId>>,
+    // creg名 -> (宣言順のclbit)
+    cregs: HashMap<String, Vec<ClbitId>>,
+    circuit: Circuit,
+}
+
+impl Parser {
+    fn position(&self) -> Position {
+        self.tokens
+            .get(self.index)
+            .map(|(_, position)| *position)
+            .unwrap_or(self.end)
+    }
+    fn next(&mut self) -> Result<(Token, Position), ParseError> {
+        let token = self
+            .tokens
+            .get(self.index)
+            .cloned()
+            .ok_or_else(|| ParseError::new(self.end, "unexpected end of input"))?;
+        self.index += 1;
+        Ok(token)
+    }
+    fn expect_symbol(&mut self, symbol: char) -> Result<(), ParseError> {
+        match self.next()? {
+            (Token::Symbol(c), _) if c == symbol => Ok(()),
+            (token, position) => Err(ParseError::new(
+                position,
+                format!("expected '{}', found {:?}", symbol, token),
+            )),
+        }
+    }
+    fn expect_ident(&mut self) -> Result<(String, Position), ParseError> {
+        match self.next()? {
+            (Token::Ident(ident), position) => Ok((ident, position)),
+            (token, position) => Err(ParseError::new(
+                position,
+                format!("expected identifier, found {:?}", token),
+            )),
+        }
+    }
+    fn expect_index(&mut self) -> Result<usize, ParseError> {
+        match self.next()? {
+            (Token::Number(number), position) => number.parse::<usize>().map_err(|_| {
+                ParseError::new(position, format!("expected integer, found {}", number))
+            }),
+            (token, position) => Err(ParseError::new(
+                position,
+                format!("expected integer, found {:?}", token),
+            )),
+        }
+    }
+    fn peek_symbol(&self, symbol: char) -> bool {
+        matches!(self.tokens.get(self.index), Some((Token::Symbol(c), _)) if *c == symbol)
+    }
+
+    // expression := term (('+' | '-') term)*
+    fn expression(&mut self) -> Result<Value, ParseError> {
+        let mut value = self.term()?;
+        while self.peek_symbol('+') || self.peek_symbol('-') {
+            let operator = if self.peek_symbol('+') { '+' } else { '-' };
+            self.index += 1;
+            value = value.apply(operator, self.term()?);
+        }
+        Ok(value)
+    }
+    // term := factor (('*' | '/') factor)*
+    fn term(&mut self) -> Result<Value, ParseError> {
+        let mut value = self.factor()?;
+        while self.peek_symbol('*') || self.peek_symbol('/') {
+            let operator = if self.peek_symbol('*') { '*' } else { '/' };
+            let position = self.position();
+            self.index += 1;
+            let other = self.factor()?;
+            if operator == '/' && other.to_f64() == 0.0 {
+                return Err(ParseError::new(position, "division by zero"));
+            }
+            value = value.apply(operator, other);
+        }
+        Ok(value)
+    }
+    // factor := '-' factor | '(' expression ')' | number | pi
+    fn factor(&mut self) -> Result<Value, ParseError> {
+        match self.next()? {
+            (Token::Symbol('-'), _) => Ok(self.factor()?.neg()),
+            (Token::Symbol('('), _) => {
+                let value = self.expression()?;
+                self.expect_symbol(')')?;
+                Ok(value)
+            }
+            (Token::Ident(ident), _) if ident == "pi" => Ok(Value::Exact {
+                numerator: 1,
+                denominator: 1,
+                pi: true,
+            }),
+            (Token::Number(number), position) => match number.parse::<i64>() {
+                Ok(integer) => Ok(Value::Exact {
+                    numerator: integer,
+                    denominator: 1,
+                    pi: false,
+                }),
+                Err(_) => number.parse::<f64>().map(Value::Float).map_err(|_| {
+                    ParseError::new(position, format!("expected number, found {}", number))
+                }),
+            },
+            (token, position) => Err(ParseError::new(
+                position,
+                format!("expected expression, found {:?}", token),
+            )),
+        }
+    }
+    // (expression, ...)
+    fn parameters(&mut self) -> Result<Vec<Angle>, ParseError> {
+        let mut parameters = Vec::new();
+        self.expect_symbol('(')?;
+        loop {
+            parameters.push(self.expression()?.to_angle());
+            match self.next()? {
+                (Token::Symbol(','), _) => continue,
+                (Token::Symbol(')'), _) => return Ok(parameters),
+                (token, position) => {
+                    return Err(ParseError::new(
+                        position,
+                        format!("expected ',' or ')', found {:?}", token),
+                    ))
+                }
+            }
+        }
+    }
+
+    fn parse(&mut self) -> Result<(), ParseError> {
+        while self.index < self.tokens.len() {
+            self.statement()?;
+        }
+        Ok(())
+    }
+    fn statement(&mut self) -> Result<(), ParseError> {
+        let (keyword, position) = self.expect_ident()?;
+        match keyword.as_str() {
+            "OPENQASM" => {
+                match self.next()? {
+                    (Token::Number(version), _) if version == "2.0" => {}
+                    (token, position) => {
+                        return Err(ParseError::new(
+                            position,
+                            format!("unsupported OPENQASM version {:?}", token),
+                        ))
+                    }
+                }
+                self.expect_symbol(';')
+            }
+            "include" => {
+                match self.next()? {
+                    (Token::Str(_), _) => {}
+                    (token, position) => {
+                        return Err(ParseError::new(
+                            position,
+                            format!("expected file name, found {:?}", token),
+                        ))
+                    }
+                }
+                self.expect_symbol(';')
+            }
+            "qreg" => self.qreg(),
+            "creg" => self.creg(),
+            "measure" => self.measure(),
+            "reset" => self.reset(position),
+            "if" => self.conditioned(),
+            // barrierはスケジューリングに影響しないので読み飛ばす
+            "barrier" => {
+                self.arguments()?;
+                Ok(())
+            }
+            gate => self.gate(gate, position),
+        }
+    }
+    // name[size];
+    fn declaration(&mut self) -> Result<(String, usize), ParseError> {
+        let (name, position) = self.expect_ident()?;
+        self.expect_symbol('[')?;
+        let size = self.expect_index()?;
+        self.expect_symbol(']')?;
+        self.expect_symbol(';')?;
+        if self.registers.contains_key(&name) || self.cregs.contains_key(&name) {
+            return Err(ParseError::new(
+                position,
+                format!("register {} is already declared", name),
+            ));
+        }
+        Ok((name, size))
+    }
+    fn qreg(&mut self) -> Result<(), ParseError> {
+        let position = self.position();
+        let (name, size) = self.declaration()?;
+        let qubits = self
+            .circuit
+            .register(&name, size)
+            .map_err(|error| ParseError::new(position, error.to_string()))?;
+        self.registers.insert(name, qubits);
+        Ok(())
+    }
+    fn creg(&mut self) -> Result<(), ParseError> {
+        let (name, size) = self.declaration()?;
+        let clbits = (0..size)
+            .map(|i| self.circuit.add_clbit(&format!("{}_{}", name, i)))
+            .collect();
+        self.cregs.insert(name, clbits);
+        Ok(())
+    }
+    // measure qubit -> clbit;
+    // register同士の場合は同じ大きさである必要がある
+    fn measure(&mut self) -> Result<(), ParseError> {
+        let qubit_argument = self.argument()?;
+        self.expect_symbol('-')?;
+        self.expect_symbol('>')?;
+        let clbit_argument = self.argument()?;
+        self.expect_symbol(';')?;
+        let qubits = self.resolve(&[qubit_argument])?;
+        let clbits = self.resolve_clbits(&clbit_argument)?;
+        if qubits.len() != clbits.len() {
+            return Err(ParseError::new(
+                clbit_argument.1,
+                "registers of different sizes cannot be broadcast",
+            ));
+        }
+        qubits
+            .iter()
+            .zip(clbits)
+            .for_each(|(qubit, clbit)| self.circuit.measure(qubit[0], clbit));
+        Ok(())
+    }
+    fn reset(&mut self, position: Position) -> Result<(), ParseError> {
+        let arguments = self.arguments()?;
+        if arguments.len() != 1 {
+            return Err(ParseError::new(
+                position,
+                format!("reset takes 1 qubit but {} were given", arguments.len()),
+            ));
+        }
+        self.resolve(&arguments)?
+            .iter()
+            .for_each(|qubits| self.circuit.reset(qubits[0]));
+        Ok(())
+    }
+    // if(creg==1) gate qubit;
+    // IRの条件は1 bitなので、1 bitのcregが1の場合だけを扱う
+    fn conditioned(&mut self) -> Result<(), ParseError> {
+        self.expect_symbol('(')?;
+        let (name, position) = self.expect_ident()?;
+        self.expect_symbol('=')?;
+        self.expect_symbol('=')?;
+        let value = self.expect_index()?;
+        self.expect_symbol(')')?;
+        let clbit = match self.cregs.get(&name) {
+            None => {
+                return Err(ParseError::new(
+                    position,
+                    format!("register {} is not declared", name),
+                ))
+            }
+            Some(clbits) if clbits.len() == 1 && value == 1 => clbits[0],
+            Some(_) => {
+                return Err(ParseError::new(
+                    position,
+                    "only if(c==1) on a 1-bit register is supported",
+                ))
+            }
+        };
+        let (gate, position) = self.expect_ident()?;
+        let (parameters, applications) = self.gate_applications(&gate, position)?;
+        let primitive_gate = match primitive_gate(&gate, &parameters) {
+            Some(primitive_gate) => primitive_gate,
+            None => {
+                return Err(ParseError::new(
+                    position,
+                    format!("{} cannot be classically controlled", gate),
+                ))
+            }
+        };
+        applications.iter().for_each(|qubits| {
+            self.circuit
+                .conditioned_gate(qubits[0], clbit, primitive_gate)
+        });
+        Ok(())
+    }
+    // name か name[index]
+    fn argument(&mut self) -> Result<(Argument, Position), ParseError> {
+        let (name, position) = self.expect_ident()?;
+        if self.peek_symbol('[') {
+            self.expect_symbol('[')?;
+            let index = self.expect_index()?;
+            self.expect_symbol(']')?;
+            Ok((Argument::Indexed(name, index), position))
+        } else {
+            Ok((Argument::Register(name), position))
+        }
+    }
+    // ;までの引数列を読む
+    fn arguments(&mut self) -> Result<Vec<(Argument, Position)>, ParseError> {
+        let mut arguments = Vec::new();
+        loop {
+            arguments.push(self.argument()?);
+            match self.next()? {
+                (Token::Symbol(','), _) => continue,
+                (Token::Symbol(';'), _) => return Ok(arguments),
+                (token, position) => {
+                    return Err(ParseError::new(
+                        position,
+                        format!("expected ',' or ';', found {:?}", token),
+                    ))
+                }
+            }
+        }
+    }
+    // 引数を実際のqubitに解決する
+    // register全体が渡された場合はbroadcastする
+    fn resolve(&self, arguments: &[(Argument, Position)]) -> Result<Vec<Vec<QubitId>>, ParseError> {
+        let mut width = None;
+        let resolved = arguments
+            .iter()
+            .map(|(argument, position)| {
+                let name = match argument {
+                    Argument::Register(name) | Argument::Indexed(name, _) => name,
+                };
+                let register = self.registers.get(name).ok_or_else(|| {
+                    ParseError::new(*position, format!("register {} is not declared", name))
+                })?;
+                match argument {
+                    Argument::Indexed(_, index) => register
+                        .get(*index)
+                        .map(|qubit| Some(*qubit))
+                        .ok_or_else(|| {
+                            ParseError::new(
+                                *position,
+                                format!("index {} is out of range for {}", index, name),
+                            )
+                        }),
+                    Argument::Register(_) => {
+                        if width.is_some() && width != Some(register.len()) {
+                            return Err(ParseError::new(
+                                *position,
+                                "registers of different sizes cannot be broadcast",
+                            ));
+                        }
+                        width = Some(register.len());
+                        Ok(None)
+                    }
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let applications = (0..width.unwrap_or(1))
+            .map(|i| {
+                resolved
+                    .iter()
+                    .zip(arguments.iter())
+                    .map(|(qubit, (argument, _))| match (qubit, argument) {
+                        (Some(qubit), _) => *qubit,
+                        (None, Argument::Register(name)) => self.registers[name][i],
+                        (None, Argument::Indexed(name, index)) => self.registers[name][*index],
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        Ok(applications)
+    }
+    fn resolve_clbits(
+        &self,
+        (argument, position): &(Argument, Position),
+    ) -> Result<Vec<ClbitId>, ParseError> {
+        let name = match argument {
+            Argument::Register(name) | Argument::Indexed(name, _) => name,
+        };
+        let clbits = self.cregs.get(name).ok_or_else(|| {
+            ParseError::new(*position, format!("register {} is not declared", name))
+        })?;
+        match argument {
+            Argument::Register(_) => Ok(clbits.clone()),
+            Argument::Indexed(_, index) => clbits.get(*index).map(|c| vec![*c]).ok_or_else(|| {
+                ParseError::new(
+                    *position,
+                    format!("index {} is out of range for {}", index, name),
+                )
+            }),
+        }
+    }
+    fn gate(&mut self, gate: &str, position: Position) -> Result<(), ParseError> {
+        let (parameters, applications) = self.gate_applications(gate, position)?;
+        applications.into_iter().try_for_each(|qubits| {
+            apply_gate(&mut self.circuit, gate, &parameters, qubits)
+                .map_err(|e| ParseError::new(position, e.to_string()))
+        })
+    }
+    // パラメータと引数を読み、ゲートを適用するqubitの組を返す
+    fn gate_applications(
+        &mut self,
+        gate: &str,
+        position: Position,
+    ) -> Result<(Vec<Angle>, Vec<Vec<QubitId>>), ParseError> {
+        // (qubitの数, パラメータの数)
+        let (arity, parameter_count) = match gate {
+            "z" | "h" | "x" | "t" | "tdg" | "s" | "sdg" => (1, 0),
+            "rz" | "rx" | "ry" => (1, 1),
+            "u3" | "U" => (1, 3),
+            "cx" | "CX" | "cz" | "swap" => (2, 0),
+            "ccx" => (3, 0),
+            _ => {
+                return Err(ParseError::new(
+                    position,
+                    format!("unsupported statement {}", gate),
+                ))
+            }
+        };
+        let parameters = if self.peek_symbol('(') {
+            if parameter_count == 0 {
+                return Err(ParseError::new(
+                    self.position(),
+                    format!("{} does not take parameters", gate),
+                ));
+            }
+            self.parameters()?
+        } else {
+            Vec::new()
+        };
+        if parameters.len() != parameter_count {
+            return Err(ParseError::new(
+                position,
+                format!(
+                    "{} takes {} parameters but {} were given",
+                    gate,
+                    parameter_count,
+                    parameters.len()
+                ),
+            ));
+        }
+        let arguments = self.arguments()?;
+        if arguments.len() != arity {
+            return Err(ParseError::new(
+                position,
+                format!(
+                    "{} takes {} qubits but {} were given",
+                    gate,
+                    arity,
+                    arguments.len()
+                ),
+            ));
+        }
+        let applications = self.resolve(&arguments)?;
+        for qubits in applications.iter() {
+            let distinct = qubits
+                .iter()
+                .enumerate()
+                .all(|(i, q)| qubits[..i].iter().all(|other| other != q));
+            if !distinct {
+                return Err(ParseError::new(
+                    position,
+                    format!("{} is applied to the same qubit twice", gate),
+                ));
+            }
+        }
+        Ok((parameters, applications))
+    }
+}
+
+// 1 qubitのゲートはそのままprimitiveになる
+fn primitive_gate(gate: &str, parameters: &[Angle]) -> Option<PrimitiveGate> {
+    match gate {
+        "rz" => Some(PrimitiveGate::Rz(parameters[0])),
+        "rx" => Some(PrimitiveGate::Rx(parameters[0])),
+        "ry" => Some(PrimitiveGate::Ry(parameters[0])),
+        "u3" | "U" => Some(PrimitiveGate::U(
+            parameters[0],
+            parameters[1],
+            parameters[2],
+        )),
+        "z" => Some(PrimitiveGate::Z),
+        "h" => Some(PrimitiveGate::H),
+        "x" => Some(PrimitiveGate::X),
+        "t" => Some(PrimitiveGate::T),
+        "tdg" => Some(PrimitiveGate::TDag),
+        "s" => Some(PrimitiveGate::S),
+        "sdg" => Some(PrimitiveGate::SDag),
+        _ => None,
+    }
+}
+
+// 1つのゲートをprimitiveに展開する
+fn apply_gate(
+    circuit: &mut Circuit,
+    gate: &str,
+    parameters: &[Angle],
+    qubits: Vec<QubitId>,
+) -> crate::error::Result<()> {
+    if let Some(primitive_gate) = primitive_gate(gate, parameters) {
+        circuit.gate(qubits[0], primitive_gate);
+        return Ok(());
+    }
+    match gate {
+        "cx" | "CX" => cnot(circuit, qubits[0], qubits[1]),
+        "cz" => {
+            circuit.gate(qubits[1], PrimitiveGate::H);
+            cnot(circuit, qubits[0], qubits[1])?;
+            circuit.gate(qubits[1], PrimitiveGate::H);
+            Ok(())
+        }
+        "swap" => {
+            cnot(circuit, qubits[0], qubits[1])?;
+            cnot(circuit, qubits[1], qubits[0])?;
+            cnot(circuit, qubits[0], qubits[1])
+        }
+        // gates::ccxの正確なClifford+T分解(T 7個)を使う
+        // gates::toffoliは相対位相が残るので使えない
+        "ccx" => ccx(circuit, qubits[0], qubits[1], qubits[2]),
+        _ => unreachable!("arity check rejects unknown gates"),
+    }
+}
+
+// qubitは宣言順に並び、idは"{register}_{index}"になる
+pub fn from_qasm(source: &str) -> Result<Circuit, ParseError> {
+    let tokens = tokenize(source)?;
+    let end = Position {
+        line: source.lines().count().max(1),
+        column: source.lines().last().map(|l| l.len() + 1).unwrap_or(1),
+    };
+    let mut parser = Parser {
+        tokens,
+        index: 0,
+        end,
+        registers: HashMap::new(),
+        cregs: HashMap::new(),
+        circuit: Circuit::new(),
+    };
+    parser.parse()?;
+    Ok(parser.circuit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitive::NodeType;
+    use crate::qasm::to_qasm::to_qasm;
+    use crate::simulator::equivalence::equivalent;
+    #[test]
+    fn round_trip() {
+        let qasm_string = "OPENQASM 2.0;\n".to_string()
+            + "include \"qelib1.inc\";\n"
+            + "qreg q[2];\n"
+            + "z q[0];\n"
+            + "x q[1];\n"
+            + "cx q[0], q[1];";
+        let circuit = from_qasm(&qasm_string).unwrap();
+        assert_eq!(circuit.qubits[0].id, "q_0");
+        assert_eq!(to_qasm(&circuit).to_string(), qasm_string);
+    }
+    #[test]
+    fn registers_and_expansions() {
+        let source = "OPENQASM 2.0;\n\
+            qreg a[2];\n\
+            qreg b[1];\n\
+            // comment\n\
+            h a; cz a[0], b[0];\n\
+            swap a[1], b[0];\n\
+            barrier a, b;\n\
+            ccx a[0], a[1], b[0];\n";
+        let circuit = from_qasm(source).unwrap();
+        let ids = circuit
+            .qubits
+            .iter()
+            .map(|q| q.id.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["a_0", "a_1", "b_0"]);
+        let file = to_qasm(&circuit);
+        // h 2個 + cz(h 2個, cx 1個) + swap(cx 3個) + ccx(15個)
+        assert_eq!(file.operations.len(), 2 + 3 + 3 + 15);
+    }
+    #[test]
+    fn ccx_is_exact() {
+        let parsed = from_qasm("qreg q[3];\nccx q[0], q[1], q[2];").unwrap();
+        let mut circuit = Circuit::new();
+        let qs = circuit.register("q", 3).unwrap();
+        ccx(&mut circuit, qs[0], qs[1], qs[2]).unwrap();
+        assert_eq!(equivalent(&to_qasm(&parsed), &to_qasm(&circuit)), Ok(true));
+        assert_eq!(parsed.stats().t_count, 7);
+    }
+    #[test]
+    fn rotations() {
+        let qasm_string = "OPENQASM 2.0;\n".to_string()
+            + "include \"qelib1.inc\";\n"
+            + "qreg q[1];\n"
+            + "rz(pi/4) q[0];\n"
+            + "rx(-3*pi/4) q[0];\n"
+            + "ry(0.5) q[0];\n"
+            + "u3(pi, 0, -pi/2) q[0];";
+        let circuit = from_qasm(&qasm_string).unwrap();
+        assert_eq!(to_qasm(&circuit).to_string(), qasm_string);
+        // πの有理数倍は分数のまま約分される
+        let circuit = from_qasm("qreg q[1];\nrz(2*pi/8 + (pi/4)) q[0];").unwrap();
+        let gate = &circuit.operations[0].node_type;
+        let expected = NodeType::PrimitiveGate(PrimitiveGate::Rz(Angle::pi_ratio(1, 2).unwrap()));
+        assert_eq!(gate, &expected);
+        assert!(from_qasm("qreg q[1];\nrz q[0];").is_err());
+        assert!(from_qasm("qreg q[1];\nrz(pi/0) q[0];").is_err());
+        assert!(from_qasm("qreg q[1];\nh(pi) q[0];").is_err());
+    }
+    #[test]
+    fn exponent_literals() {
+        let circuit = from_qasm("qreg q[1];\nrz(1e-05) q[0];\nrx(2.5E+1*pi) q[0];").unwrap();
+        let gates = circuit
+            .operations
+            .iter()
+            .map(|operation| operation.node_type.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            gates,
+            vec![
+                NodeType::PrimitiveGate(PrimitiveGate::Rz(Angle::Radians(1e-05))),
+                NodeType::PrimitiveGate(PrimitiveGate::Rx(Angle::Radians(25.0 * PI))),
+            ]
+        );
+        assert!(from_qasm("qreg q[1];\nrz(1e) q[0];").is_err());
+        assert!(from_qasm("qreg q[1];\nrz(1e+) q[0];").is_err());
+    }
+    #[test]
+    fn i64_min_does_not_overflow() {
+        let min = "(-9223372036854775807-1)";
+        [
+            format!("rz(0/{}) q[0];", min),
+            format!("rz({}/(-1)) q[0];", min),
+            format!("rz(-{}*pi) q[0];", min),
+            format!("rz(pi-{}*pi) q[0];", min),
+            format!("rz({}*pi/{}) q[0];", min, min),
+        ]
+        .iter()
+        .for_each(|line| {
+            let circuit = from_qasm(&format!("qreg q[1];\n{}", line)).unwrap();
+            assert_eq!(circuit.operations.len(), 1, "{}", line);
+            // 逆回路で角度の符号を反転してもあふれない
+            assert!(circuit.inverse().is_ok(), "{}", line);
+        });
+        assert!(from_qasm(&format!("qreg q[1];\nrz(1/{}) q[0];", min)).is_ok());
+    }
+    #[test]
+    fn classical() {
+        let qasm_string = "OPENQASM 2.0;\n".to_string()
+            + "include \"qelib1.inc\";\n"
+            + "qreg q[2];\n"
+            + "creg c0[1];\n"
+            + "h q[0];\n"
+            + "measure q[0] -> c0[0];\n"
+            + "reset q[0];\n"
+            + "if(c0==1) s q[1];";
+        let circuit = from_qasm(&qasm_string).unwrap();
+        assert_eq!(circuit.clbits, vec!["c0_0"]);
+        assert_eq!(to_qasm(&circuit).to_string(), qasm_string);
+        assert!(from_qasm("qreg q[2];\ncreg c[2];\nmeasure q -> c;").is_ok());
+        assert!(from_qasm("qreg q[2];\ncreg c[1];\nmeasure q -> c;").is_err());
+        assert!(from_qasm("qreg q[2];\ncreg c[2];\nif(c==1) x q[0];").is_err());
+        assert!(from_qasm("qreg q[2];\ncreg c[1];\nif(c==1) cx q[0], q[1];").is_err());
+    }
+    #[test]
+    fn located_errors() {
+        let error = from_qasm("OPENQASM 2.0;\nqreg q[1];\n  cu1(0) q[0], q[1];").unwrap_err();
+        assert_eq!((error.line, error.column), (3, 3));
+        let error = from_qasm("qreg q[1];\nx q[1];").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 3));
+        let error = from_qasm("qreg q[2];\ncx q[0], r[1];").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 10));
+        let error = from_qasm("qreg q[2];\ncx q[0], q[0];").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 1));
+        assert!(from_qasm("qreg q[2];\nh q[0]").is_err());
+    }
+}
@@ -1,4 +1,5 @@
 // QASM 2.0におけるオペレーションのファイル
+use crate::primitive::{Angle, ClbitId, PrimitiveGate};
 
 // QASM
 // 実質値なのでCopyもつける
@@ -16,10 +17,92 @@ pub enum Operation {
     TDag(QubitId),
     S(QubitId),
     SDag(QubitId),
+    // 回転ゲートは角度を先に持つ
+    Rz(Angle, QubitId),
+    Rx(Angle, QubitId),
+    Ry(Angle, QubitId),
+    U(Angle, Angle, Angle, QubitId),
     // 追加でCNOTがある
     CX(QubitId, QubitId),
+    // 古典bitは1bitずつのcreg c0, c1, ...になる
+    Measure(QubitId, ClbitId),
+    Reset(QubitId),
+    // clbitが1のときだけかける1 qubitゲート
+    If(ClbitId, PrimitiveGate, QubitId),
 }
 
+impl Operation {
+    pub fn gate(gate: PrimitiveGate, target: QubitId) -> Operation {
+        match gate {
+            PrimitiveGate::Z => Operation::Z(target),
+            PrimitiveGate::H => Operation::H(target),
+            PrimitiveGate::X => Operation::X(target),
+            PrimitiveGate::T => Operation::T(target),
+            PrimitiveGate::TDag => Operation::TDag(target),
+            PrimitiveGate::S => Operation::S(target),
+            PrimitiveGate::SDag => Operation::SDag(target),
+            PrimitiveGate::Rz(angle) => Operation::Rz(angle, target),
+            PrimitiveGate::Rx(angle) => Operation::Rx(angle, target),
+            PrimitiveGate::Ry(angle) => Operation::Ry(angle, target),
+            PrimitiveGate::U(theta, phi, lambda) => Operation::U(theta, phi, lambda, target),
+        }
+    }
+    pub fn target(&self) -> QubitId {
+        match self {
+            Operation::Z(target)
+            | Operation::H(target)
+            | Operation::X(target)
+            | Operation::T(target)
+            | Operation::TDag(target)
+            | Operation::S(target)
+            | Operation::SDag(target)
+            | Operation::Rz(_, target)
+            | Operation::Rx(_, target)
+            | Operation::Ry(_, target)
+            | Operation::U(_, _, _, target)
+            | Operation::CX(_, target)
+            | Operation::Measure(target, _)
+            | Operation::Reset(target)
+            | Operation::If(_, _, target) => *target,
+        }
+    }
+    // 外部のcircuit builderに渡すための(ゲート名, qubits, 角度(radian), clbits)
+    // Measureは書き込む先、Ifは条件のbitをclbitsに持つ
+    pub fn to_tuple(&self) -> OperationTuple {
+        let qubits = match self {
+            Operation::CX(from, to) => vec![from.0 as usize, to.0 as usize],
+            _ => vec![self.target().0 as usize],
+        };
+        let (name, parameters, clbits) = match self {
+            Operation::Z(_) => ("z", vec![], vec![]),
+            Operation::H(_) => ("h", vec![], vec![]),
+            Operation::X(_) => ("x", vec![], vec![]),
+            Operation::T(_) => ("t", vec![], vec![]),
+            Operation::TDag(_) => ("tdg", vec![], vec![]),
+            Operation::S(_) => ("s", vec![], vec![]),
+            Operation::SDag(_) => ("sdg", vec![], vec![]),
+            Operation::Rz(angle, _) => ("rz", vec![angle.radians()], vec![]),
+            Operation::Rx(angle, _) => ("rx", vec![angle.radians()], vec![]),
+            Operation::Ry(angle, _) => ("ry", vec![angle.radians()], vec![]),
+            Operation::U(theta, phi, lambda, _) => (
+                "u3",
+                vec![theta.radians(), phi.radians(), lambda.radians()],
+                vec![],
+            ),
+            Operation::CX(_, _) => ("cx", vec![], vec![]),
+            Operation::Measure(_, clbit) => ("measure", vec![], vec![clbit.0]),
+            Operation::Reset(_) => ("reset", vec![], vec![]),
+            Operation::If(clbit, gate, target) => {
+                let (name, _, parameters, _) = Operation::gate(*gate, *target).to_tuple();
+                (name, parameters, vec![clbit.0])
+            }
+        };
+        (name, qubits, parameters, clbits)
+    }
+}
+
+pub type OperationTuple = (&'static str, Vec<usize>, Vec<f64>, Vec<usize>);
+
 impl ToString for Operation {
     fn to_string(&self) -> String {
         match &self {
@@ -30,7 +113,22 @@ impl ToString for Operation {
             Operation::TDag(target) => format!("tdg q[{}];", target.0),
             Operation::S(target) => format!("s q[{}];", target.0),
             Operation::SDag(target) => format!("sdg q[{}];", target.0),
+            Operation::Rz(angle, target) => format!("rz({}) q[{}];", angle, target.0),
+            Operation::Rx(angle, target) => format!("rx({}) q[{}];", angle, target.0),
+            Operation::Ry(angle, target) => format!("ry({}) q[{}];", angle, target.0),
+            Operation::U(theta, phi, lambda, target) => {
+                format!("u3({}, {}, {}) q[{}];", theta, phi, lambda, target.0)
+            }
             Operation::CX(from, to) => format!("cx q[{}], q[{}];", from.0, to.0),
+            Operation::Measure(target, clbit) => {
+                format!("measure q[{}] -> c{}[0];", target.0, clbit.0)
+            }
+            Operation::Reset(target) => format!("reset q[{}];", target.0),
+            Operation::If(clbit, gate, target) => format!(
+                "if(c{}==1) {}",
+                clbit.0,
+                Operation::gate(*gate, *target).to_string()
+            ),
         }
     }
 }
@@ -39,6 +137,7 @@ impl ToString for Operation {
 // QASM 2.0のファイルは基本的には操作の集合
 pub struct File {
     pub qubit_count: usize,
+    pub clbit_count: usize,
     pub operations: Vec<Operation>,
 }
 
@@ -46,7 +145,10 @@ impl ToString for File {
     fn to_string(&self) -> String {
         let header = "OPENQASM 2.0;\n";
         let includer = "include \"qelib1.inc\";\n";
-        let qubit_declaration = format!("qreg q[{}];\n", self.qubit_count);
+        let qubit_declaration = format!("qreg q[{}];\n", self.qubit_count)
+            + &(0..self.clbit_count)
+                .map(|i| format!("creg c{}[1];\n", i))
+                .collect::<String>();
         let qasm_strings = self
             .operations
             .iter()
@@ -67,6 +169,7 @@ mod tests {
     fn always_pass() {
         let file = File {
             qubit_count: 2,
+            clbit_count: 0,
             operations: vec![
                 Operation::Z(QubitId(0)),
                 Operation::X(QubitId(1)),
@@ -81,4 +184,39 @@ mod tests {
             + "cx q[0], q[1];";
         assert_eq!(file.to_string(), qasm_string);
     }
+    #[test]
+    fn tuples() {
+        assert_eq!(
+            Operation::CX(QubitId(2), QubitId(0)).to_tuple(),
+            ("cx", vec![2, 0], vec![], vec![])
+        );
+        assert_eq!(
+            Operation::Rz(Angle::pi_ratio(1, 2).unwrap(), QubitId(1)).to_tuple(),
+            ("rz", vec![1], vec![std::f64::consts::FRAC_PI_2], vec![])
+        );
+        assert_eq!(
+            Operation::If(ClbitId(3), PrimitiveGate::S, QubitId(0)).to_tuple(),
+            ("s", vec![0], vec![], vec![3])
+        );
+    }
+    #[test]
+    fn classical() {
+        let file = File {
+            qubit_count: 2,
+            clbit_count: 1,
+            operations: vec![
+                Operation::Measure(QubitId(0), ClbitId(0)),
+                Operation::If(ClbitId(0), PrimitiveGate::X, QubitId(1)),
+                Operation::Reset(QubitId(0)),
+            ],
+        };
+        let qasm_string = "OPENQASM 2.0;\n".to_string()
+            + "include \"qelib1.inc\";\n"
+            + "qreg q[2];\n"
+            + "creg c0[1];\n"
+            + "measure q[0] -> c0[0];\n"
+            + "if(c0==1) x q[1];\n"
+            + "reset q[0];";
+        assert_eq!(file.to_string(), qasm_string);
+    }
 }
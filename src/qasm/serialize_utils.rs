@@ -1,55 +1,52 @@
 use super::operations;
 use crate::primitive;
 use itertools::Itertools;
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::collections::HashMap;
 
-type PrimitiveQubitId = String;
-
-pub struct QubitIdMap(HashMap<PrimitiveQubitId, operations::QubitId>);
+pub struct QubitIdMap(HashMap<primitive::QubitId, operations::QubitId>);
 
 impl QubitIdMap {
-    pub fn from_cells(qubit_cells: &Vec<primitive::QubitCell>) -> QubitIdMap {
+    pub fn from_qubits(qubits: &[primitive::QubitRef<'_>]) -> QubitIdMap {
         let mut qubit_id_map = HashMap::new();
-        qubit_cells
-            .iter()
-            .enumerate()
-            .for_each(|(qubit_index, cell)| {
-                let qubit_name: PrimitiveQubitId = cell.borrow().id.clone();
-                let qubit_id = operations::QubitId(qubit_index as i32);
-                qubit_id_map.insert(qubit_name, qubit_id);
-            });
+        qubits.iter().enumerate().for_each(|(qubit_index, qubit)| {
+            let qubit_id = operations::QubitId(qubit_index as i32);
+            qubit_id_map.insert(qubit.id, qubit_id);
+        });
         QubitIdMap(qubit_id_map)
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct NextOperation {
-    pub qubit_cell: primitive::QubitCell,
+pub struct NextOperation<'a> {
+    pub qubit: primitive::QubitRef<'a>,
     pub operation_index: usize,
     // ControlFromだった場合、次が何番目なのか
     pub cnot_count: i32,
 }
 
-impl NextOperation {
-    fn new(qubit_cell: &primitive::QubitCell) -> Option<Self> {
-        if qubit_cell.clone().borrow().operations.len() == 0 {
+impl<'a> NextOperation<'a> {
+    fn new(qubit: &primitive::QubitRef<'a>) -> Option<Self> {
+        if qubit.operations().is_empty() {
             return None;
         }
         Some(Self {
-            qubit_cell: qubit_cell.clone(),
+            qubit: *qubit,
             operation_index: 0,
             cnot_count: 0,
         })
     }
+    fn operation(&self) -> primitive::OperationRef<'a> {
+        self.qubit.operation(self.operation_index).unwrap()
+    }
     fn has_next(&self) -> bool {
-        let qubit_cell = self.qubit_cell.clone();
-        let operation_max_count = qubit_cell.borrow().operations.len();
-        let last_cnot_count = match qubit_cell.borrow().operations[operation_max_count - 1]
-            .clone()
-            .borrow()
-            .node_type
+        let operation_max_count = self.qubit.operations().len();
+        let last_cnot_count = match self
+            .qubit
+            .operation(operation_max_count - 1)
+            .unwrap()
+            .node_type()
         {
-            primitive::NodeType::Control(count) => count,
+            primitive::NodeType::Control(count) => *count,
             _ => 0,
         };
         (self.operation_index + 1 < operation_max_count) || (self.cnot_count + 1 < last_cnot_count)
@@ -60,10 +57,7 @@ impl NextOperation {
         }
         if self.is_control() {
             // controlだった場合、カウント最大かどうかが判定の分かれ目になる
-            let qubit_cell = self.qubit_cell.clone();
-            let operation = qubit_cell.borrow().operations[self.operation_index].clone();
-            let node_type = &operation.borrow().node_type;
-            let max_cnot_count = match node_type {
+            let max_cnot_count = match self.operation().node_type() {
                 primitive::NodeType::Control(count) => *count,
                 // primitive::NodeType::ControlledNotの可能性もある
                 _ => 0,
@@ -82,45 +76,32 @@ impl NextOperation {
             self.operation_index += 1;
         }
         // 次がcnotのfromでcountが0の場合、さらに次に進む
-        let current_operation_index = self.operation_index;
-        let qubit_cell = self.qubit_cell.clone();
-        let current_operation = &qubit_cell.borrow().operations[current_operation_index];
-        let current_operation_node_type = &current_operation.borrow().node_type;
-        if let primitive::NodeType::Control(count) = current_operation_node_type {
+        if let primitive::NodeType::Control(count) = self.operation().node_type() {
             if *count == 0 {
                 self.next();
             }
         }
     }
     fn is_control(&self) -> bool {
-        let qubit_cell = self.qubit_cell.clone();
-        let operation = qubit_cell.borrow().operations[self.operation_index].clone();
-        let node_type = &operation.borrow().node_type;
-        match node_type {
+        match self.operation().node_type() {
             primitive::NodeType::Control(_) => true,
             primitive::NodeType::ControlledNot(_, _) => true,
             _ => false,
         }
     }
-    fn extract_parent_operation(&self) -> Option<(Rc<RefCell<primitive::Operation>>, i32)> {
-        let qubit_cell = self.qubit_cell.clone();
-        let operation = qubit_cell.borrow().operations[self.operation_index].clone();
-        let node_type = &operation.borrow().node_type;
-        let parent_info = match node_type {
-            primitive::NodeType::ControlledNot(weak, position) => {
-                Some((weak.upgrade().unwrap(), *position))
-            }
+    fn extract_parent_operation(&self) -> Option<(primitive::OpId, i32)> {
+        match self.operation().node_type() {
+            primitive::NodeType::ControlledNot(parent, position) => Some((*parent, *position)),
             _ => None,
-        };
-        parent_info
+        }
     }
     fn is_control_pair(na: &Self, nb: &Self) -> bool {
         // どちらかがcontrolでなければ違う
         if !(na.is_control() && nb.is_control()) {
             return false;
         }
-        let operation_na = na.qubit_cell.clone().borrow().operations[na.operation_index].clone();
-        let operation_nb = nb.qubit_cell.clone().borrow().operations[nb.operation_index].clone();
+        let operation_na = na.operation().id;
+        let operation_nb = nb.operation().id;
         let parent_operation_na = na.extract_parent_operation();
         let parent_operation_nb = nb.extract_parent_operation();
 
@@ -144,25 +125,21 @@ impl NextOperation {
         if self.is_control() {
             return None;
         }
-        let qubit_cell = self.qubit_cell.clone();
-        let operation = qubit_cell.borrow().operations[self.operation_index].clone();
-        let node_type = &operation.borrow().node_type;
-        let gate = match node_type {
-            primitive::NodeType::PrimitiveGate(gate) => gate,
-            _ => return None,
-        };
-        let qubit_id_string = &qubit_cell.borrow().id;
-        let qubit_id_i32 = qubit_id_map.0.get(qubit_id_string).unwrap();
+        let qubit_id_i32 = *qubit_id_map.0.get(&self.qubit.id).unwrap();
         // あとは変換するだけ
-        Some(match gate {
-            primitive::PrimitiveGate::Z => operations::Operation::Z(*qubit_id_i32),
-            primitive::PrimitiveGate::H => operations::Operation::H(*qubit_id_i32),
-            primitive::PrimitiveGate::X => operations::Operation::X(*qubit_id_i32),
-            primitive::PrimitiveGate::T => operations::Operation::T(*qubit_id_i32),
-            primitive::PrimitiveGate::TDag => operations::Operation::TDag(*qubit_id_i32),
-            primitive::PrimitiveGate::S => operations::Operation::S(*qubit_id_i32),
-            primitive::PrimitiveGate::SDag => operations::Operation::SDag(*qubit_id_i32),
-        })
+        match self.operation().node_type() {
+            primitive::NodeType::PrimitiveGate(gate) => {
+                Some(operations::Operation::gate(*gate, qubit_id_i32))
+            }
+            primitive::NodeType::Measure(clbit) => {
+                Some(operations::Operation::Measure(qubit_id_i32, *clbit))
+            }
+            primitive::NodeType::Reset => Some(operations::Operation::Reset(qubit_id_i32)),
+            primitive::NodeType::Conditioned(clbit, gate) => {
+                Some(operations::Operation::If(*clbit, *gate, qubit_id_i32))
+            }
+            _ => None,
+        }
     }
     fn to_qasm_pair(
         na: &Self,
@@ -173,15 +150,10 @@ impl NextOperation {
         if !Self::is_control_pair(na, nb) {
             return None;
         }
-        let qubit_cell_na = na.qubit_cell.clone();
-        let qubit_cell_nb = nb.qubit_cell.clone();
         let parent_operation_na = na.extract_parent_operation();
         let parent_operation_nb = nb.extract_parent_operation();
-        let qubit_id_string_na = &qubit_cell_na.borrow().id;
-        let qubit_id_string_nb = &qubit_cell_nb.borrow().id;
-        let qubit_id_i32_na = qubit_id_map.0.get(qubit_id_string_na).unwrap();
-        let qubit_id_i32_nb = qubit_id_map.0.get(qubit_id_string_nb).unwrap();
-
+        let qubit_id_i32_na = qubit_id_map.0.get(&na.qubit.id).unwrap();
+        let qubit_id_i32_nb = qubit_id_map.0.get(&nb.qubit.id).unwrap();
         match (parent_operation_na, parent_operation_nb) {
             // naが親の場合
             (None, Some(_)) => {
@@ -202,22 +174,97 @@ impl NextOperation {
     }
 }
 
+// 古典bitの読み書きの順番を守るための状態
+// Measureは何回目の書き込みか、Conditionedは何回目の書き込みを読むか(世代)を持つ
+#[derive(Debug, Clone, Default)]
+struct ClassicalOrder {
+    generations: HashMap<primitive::OpId, (primitive::ClbitId, usize)>,
+    // (clbit, 世代) -> その値を読む操作の数
+    readers: HashMap<(primitive::ClbitId, usize), usize>,
+    // 書き出し済みの測定の数
+    measured: HashMap<primitive::ClbitId, usize>,
+    // 書き出し済みの読み込みの数
+    read: HashMap<(primitive::ClbitId, usize), usize>,
+}
+
+impl ClassicalOrder {
+    // 追加した順を古典bitの上での実行順とする
+    fn new(circuit: &primitive::Circuit) -> Self {
+        let mut order = Self::default();
+        let mut measured = HashMap::new();
+        circuit
+            .operations
+            .iter()
+            .enumerate()
+            .for_each(|(index, operation)| match operation.node_type {
+                primitive::NodeType::Measure(clbit) => {
+                    let generation = measured.entry(clbit).or_insert(0);
+                    *generation += 1;
+                    order
+                        .generations
+                        .insert(primitive::OpId(index), (clbit, *generation));
+                }
+                primitive::NodeType::Conditioned(clbit, _) => {
+                    let generation = *measured.get(&clbit).unwrap_or(&0);
+                    order
+                        .generations
+                        .insert(primitive::OpId(index), (clbit, generation));
+                    *order.readers.entry((clbit, generation)).or_insert(0) += 1;
+                }
+                _ => {}
+            });
+        order
+    }
+    fn is_ready(&self, operation: &primitive::OperationRef<'_>) -> bool {
+        let (clbit, generation) = match self.generations.get(&operation.id) {
+            Some(generation) => *generation,
+            None => return true,
+        };
+        let measured = *self.measured.get(&clbit).unwrap_or(&0);
+        match operation.node_type() {
+            // 前の値を読む操作がすべて終わってから書き込む
+            primitive::NodeType::Measure(_) => {
+                let key = (clbit, generation - 1);
+                measured + 1 == generation
+                    && self.read.get(&key).unwrap_or(&0) == self.readers.get(&key).unwrap_or(&0)
+            }
+            // 読む値が書き込まれてから読む
+            _ => measured == generation,
+        }
+    }
+    fn emit(&mut self, operation: &primitive::OperationRef<'_>) {
+        if let Some((clbit, generation)) = self.generations.get(&operation.id) {
+            match operation.node_type() {
+                primitive::NodeType::Measure(_) => *self.measured.entry(*clbit).or_insert(0) += 1,
+                _ => *self.read.entry((*clbit, *generation)).or_insert(0) += 1,
+            }
+        }
+    }
+}
+
 // Noneだった場合は次は存在しない
 #[derive(Debug, Clone)]
-pub struct NextOperations(Vec<Option<NextOperation>>);
+pub struct NextOperations<'a>(Vec<Option<NextOperation<'a>>>, ClassicalOrder);
 
-impl NextOperations {
-    pub fn initialize_from_cells(qubit_cells: &Vec<primitive::QubitCell>) -> Self {
+impl<'a> NextOperations<'a> {
+    pub fn initialize_from_qubits(qubits: &[primitive::QubitRef<'a>]) -> Self {
+        let classical_order = qubits
+            .first()
+            .map(|qubit| ClassicalOrder::new(qubit.circuit))
+            .unwrap_or_default();
         Self(
-            qubit_cells
-                .iter()
-                .map(|cell| NextOperation::new(&cell))
-                .collect::<Vec<_>>(),
+            qubits.iter().map(NextOperation::new).collect::<Vec<_>>(),
+            classical_order,
         )
     }
     pub fn next(&mut self) {
         // extractableなものをすべて進める
         let forwardable_index = self.pick_extractable_indexes().0;
+        forwardable_index.iter().for_each(|index| {
+            if let Some(operation) = &self.0[*index] {
+                self.1.emit(&operation.operation());
+            }
+        });
         let operations = &mut self.0;
         forwardable_index.iter().for_each(|index| {
             let operation = &mut operations[*index];
@@ -279,9 +326,16 @@ impl NextOperations {
                 }
             })
             .collect::<Vec<_>>();
+        // 古典bitを待っているものは進めない
         let single_operations_index = operation_is_control
             .iter()
-            .filter_map(|(index, is_control)| if !*is_control { Some(*index) } else { None })
+            .filter(|(index, is_control)| {
+                !*is_control
+                    && self
+                        .1
+                        .is_ready(&operations[*index].as_ref().unwrap().operation())
+            })
+            .map(|(index, _)| *index)
             .collect::<Vec<_>>();
         let raw_cx_operations_index = operation_is_control
             .iter()
@@ -326,26 +380,27 @@ mod tests {
     use super::*;
     use crate::{
         gates::toffoli,
-        primitive::{PrimitiveGate, Qubit, QubitCell},
-        util::cellize,
+        primitive::{Circuit, PrimitiveGate},
     };
-    fn get_qubit_cells() -> Vec<QubitCell> {
-        let q1 = cellize(Qubit::new("test_1"));
-        let q2 = cellize(Qubit::new("test_2"));
-        Qubit::gate(q1.clone(), PrimitiveGate::H);
-        Qubit::gate(q1.clone(), PrimitiveGate::T);
-        Qubit::gate(q2.clone(), PrimitiveGate::Z);
-        crate::gates::cnot(q1.clone(), q2.clone());
-        Qubit::gate(q2.clone(), PrimitiveGate::T);
-        Qubit::gate(q2.clone(), PrimitiveGate::H);
-        Qubit::gate(q1.clone(), PrimitiveGate::Z);
-        vec![q1.clone(), q2.clone()]
+    fn get_circuit() -> Circuit {
+        let mut circuit = Circuit::new();
+        let q1 = circuit.add_qubit("test_1").unwrap();
+        let q2 = circuit.add_qubit("test_2").unwrap();
+        circuit.gate(q1, PrimitiveGate::H);
+        circuit.gate(q1, PrimitiveGate::T);
+        circuit.gate(q2, PrimitiveGate::Z);
+        crate::gates::cnot(&mut circuit, q1, q2).unwrap();
+        circuit.gate(q2, PrimitiveGate::T);
+        circuit.gate(q2, PrimitiveGate::H);
+        circuit.gate(q1, PrimitiveGate::Z);
+        circuit
     }
     #[test]
     fn next_operation_new() {
-        let qc1 = get_qubit_cells()[0].clone();
+        let circuit = get_circuit();
+        let qc1 = circuit.qubit_refs()[0];
         let expectation = Some(NextOperation {
-            qubit_cell: qc1.clone(),
+            qubit: qc1,
             operation_index: 0,
             cnot_count: 0,
         });
@@ -353,9 +408,10 @@ mod tests {
     }
     #[test]
     fn next_operation_has_next() {
-        let qc1 = get_qubit_cells()[0].clone();
+        let circuit = get_circuit();
+        let qc1 = circuit.qubit_refs()[0];
         let nqc1 = NextOperation {
-            qubit_cell: qc1.clone(),
+            qubit: qc1,
             operation_index: 3,
             cnot_count: 0,
         };
@@ -363,17 +419,17 @@ mod tests {
     }
     #[test]
     fn next_operation_has_next_2() {
-        let qc1 = get_qubit_cells()[0].clone();
-        let qc2 = get_qubit_cells()[1].clone();
-        let qc1c = Qubit::control(qc1.clone());
-        let qc2e1 = Qubit::export(qc2.clone());
-        let qc2e2 = Qubit::export(qc2.clone());
-        let qc2e3 = Qubit::export(qc2.clone());
-        qc2e1.control_by(&qc1c);
-        qc2e2.control_by(&qc1c);
-        qc2e3.control_by(&qc1c);
+        let mut circuit = get_circuit();
+        let (qc1, qc2) = (primitive::QubitId(0), primitive::QubitId(1));
+        let qc1c = circuit.control(qc1);
+        let qc2e1 = circuit.export(qc2);
+        let qc2e2 = circuit.export(qc2);
+        let qc2e3 = circuit.export(qc2);
+        qc2e1.control_by(&mut circuit, &qc1c).unwrap();
+        qc2e2.control_by(&mut circuit, &qc1c).unwrap();
+        qc2e3.control_by(&mut circuit, &qc1c).unwrap();
         let nqc1 = NextOperation {
-            qubit_cell: qc1.clone(),
+            qubit: circuit.qubit_ref(qc1),
             operation_index: 4,
             cnot_count: 2,
         };
@@ -382,13 +438,14 @@ mod tests {
 
     #[test]
     fn toffoli_test() {
-        let q1 = cellize(Qubit::new("q1"));
-        let q2 = cellize(Qubit::new("q2"));
-        let q3 = cellize(Qubit::new("q3"));
-        toffoli(q1.clone(), q2.clone(), q3.clone());
-        let qcells = vec![q1.clone(), q2.clone(), q3.clone()];
-        let map = QubitIdMap::from_cells(&qcells);
-        let mut nops = NextOperations::initialize_from_cells(&qcells);
+        let mut circuit = Circuit::new();
+        let q1 = circuit.add_qubit("q1").unwrap();
+        let q2 = circuit.add_qubit("q2").unwrap();
+        let q3 = circuit.add_qubit("q3").unwrap();
+        toffoli(&mut circuit, q1, q2, q3).unwrap();
+        let qubits = circuit.qubit_refs();
+        let map = QubitIdMap::from_qubits(&qubits);
+        let mut nops = NextOperations::initialize_from_qubits(&qubits);
         nops.next();
         nops.next();
         nops.next();
@@ -409,31 +466,31 @@ mod tests {
         use crate::select_gates::simple_select_controls::*;
 
         let n = 1;
-        let first_qubit = cellize(Qubit::new("first"));
-        let first_control = Qubit::control(first_qubit.clone());
+        let mut circuit = Circuit::new();
         let datas = (0..n)
-            .map(|i| cellize(Qubit::new(format!("data_{}", i).as_str())))
+            .map(|i| circuit.add_qubit(format!("data_{}", i).as_str()).unwrap())
             .collect::<Vec<_>>();
         let ancillas = (0..n)
-            .map(|i| cellize(Qubit::new(format!("ancilla_{}", i).as_str())))
+            .map(|i| {
+                circuit
+                    .add_qubit(format!("ancilla_{}", i).as_str())
+                    .unwrap()
+            })
             .collect::<Vec<_>>();
-        let controls = in_over_2n(n, &first_control, datas.clone(), ancillas.clone());
-        let data_length = controls.len();
         let targets = (0..n)
-            .map(|i| cellize(Qubit::new(format!("target_{}", i).as_str())))
+            .map(|i| circuit.add_qubit(format!("target_{}", i).as_str()).unwrap())
             .collect::<Vec<_>>();
-        let random_data = generate_datas(data_length, 1);
-        inject_qrom_datas(targets.clone(), controls, random_data);
-
-        let mut qubit_cells = Vec::new();
-        qubit_cells.extend(datas.clone());
-        qubit_cells.extend(ancillas.clone());
-        qubit_cells.extend(targets.clone());
-        qubit_cells.push(first_qubit.clone());
+        let first_qubit = circuit.add_qubit("first").unwrap();
+        let first_control = circuit.control(first_qubit);
+        let controls = in_over_2n(&mut circuit, n, &first_control, &datas, &ancillas).unwrap();
+        let data_length = controls.len();
+        let random_data = generate_datas(data_length, 1, 1.0, &mut rng_from_seed(Some(0)));
+        inject_qrom_datas(&mut circuit, &targets, &controls, &random_data).unwrap();
 
+        let qubits = circuit.qubit_refs();
         // println!("{:#?}", qubits);
-        let _map = QubitIdMap::from_cells(&qubit_cells);
-        let mut nop = NextOperations::initialize_from_cells(&qubit_cells);
+        let _map = QubitIdMap::from_qubits(&qubits);
+        let mut nop = NextOperations::initialize_from_qubits(&qubits);
         // dbg!(nop.to_qasm(&_map));
 
         nop.next();
@@ -2,10 +2,12 @@ use super::operations;
 use super::serialize_utils;
 use crate::primitive;
 
-pub fn to_qasm(qubit_cells: Vec<primitive::QubitCell>) -> operations::File {
-    let qubit_count = qubit_cells.len();
-    let qubit_id_map = serialize_utils::QubitIdMap::from_cells(&qubit_cells);
-    let mut next_operations = serialize_utils::NextOperations::initialize_from_cells(&qubit_cells);
+// qubitはcircuitに追加した順に並ぶ
+pub fn to_qasm(circuit: &primitive::Circuit) -> operations::File {
+    let qubits = circuit.qubit_refs();
+    let qubit_count = qubits.len();
+    let qubit_id_map = serialize_utils::QubitIdMap::from_qubits(&qubits);
+    let mut next_operations = serialize_utils::NextOperations::initialize_from_qubits(&qubits);
     let mut qasm_operations = Vec::new();
     while next_operations.has_next() {
         // assert!(count < 1000, "Too many iterations");
@@ -18,6 +20,7 @@ pub fn to_qasm(qubit_cells: Vec<primitive::QubitCell>) -> operations::File {
     }
     operations::File {
         qubit_count,
+        clbit_count: circuit.clbits.len(),
         operations: qasm_operations,
     }
 }
@@ -26,48 +29,68 @@ pub fn to_qasm(qubit_cells: Vec<primitive::QubitCell>) -> operations::File {
 mod tests {
     use super::*;
     use crate::gates::toffoli;
-    use crate::primitive::Qubit;
-    use crate::util::cellize;
+    use crate::primitive::Circuit;
     #[test]
     fn toffoli_test() {
-        let q1 = cellize(Qubit::new("q1"));
-        let q2 = cellize(Qubit::new("q2"));
-        let q3 = cellize(Qubit::new("q3"));
-        toffoli(q1.clone(), q2.clone(), q3.clone());
-        let qasm_file = to_qasm(vec![q1, q2, q3]);
+        let mut circuit = Circuit::new();
+        let q1 = circuit.add_qubit("q1").unwrap();
+        let q2 = circuit.add_qubit("q2").unwrap();
+        let q3 = circuit.add_qubit("q3").unwrap();
+        toffoli(&mut circuit, q1, q2, q3).unwrap();
+        let qasm_file = to_qasm(&circuit);
         println!("{}", qasm_file.to_string());
     }
     #[test]
+    fn measure_before_condition() {
+        // 条件付きゲートのqubitの方が先に並んでいても、測定の後に出力される
+        let mut circuit = Circuit::new();
+        let target = circuit.add_qubit("target").unwrap();
+        let measured = circuit.add_qubit("measured").unwrap();
+        let clbit = circuit.add_clbit("m");
+        circuit.gate(measured, primitive::PrimitiveGate::H);
+        circuit.measure(measured, clbit);
+        circuit.conditioned_gate(target, clbit, primitive::PrimitiveGate::X);
+        circuit.reset(measured);
+        let qasm_string = "OPENQASM 2.0;\n".to_string()
+            + "include \"qelib1.inc\";\n"
+            + "qreg q[2];\n"
+            + "creg c0[1];\n"
+            + "h q[1];\n"
+            + "measure q[1] -> c0[0];\n"
+            + "if(c0==1) x q[0];\n"
+            + "reset q[1];";
+        assert_eq!(to_qasm(&circuit).to_string(), qasm_string);
+    }
+    #[test]
     fn uniform_layered_test() {
         use crate::select_gates::data_combine::*;
         use crate::select_gates::simple_select_controls::*;
 
         let n = 4;
-        let first_qubit = cellize(Qubit::new("first"));
-        let first_control = Qubit::control(first_qubit.clone());
+        let mut circuit = Circuit::new();
         let datas = (0..n)
-            .map(|i| cellize(Qubit::new(format!("data_{}", i).as_str())))
+            .map(|i| circuit.add_qubit(format!("data_{}", i).as_str()).unwrap())
             .collect::<Vec<_>>();
         let ancillas = (0..n)
-            .map(|i| cellize(Qubit::new(format!("ancilla_{}", i).as_str())))
+            .map(|i| {
+                circuit
+                    .add_qubit(format!("ancilla_{}", i).as_str())
+                    .unwrap()
+            })
             .collect::<Vec<_>>();
-        let controls = in_over_2n(n, &first_control, datas.clone(), ancillas.clone());
-        let data_length = controls.len();
         let targets = (0..n)
-            .map(|i| cellize(Qubit::new(format!("target_{}", i).as_str())))
+            .map(|i| circuit.add_qubit(format!("target_{}", i).as_str()).unwrap())
             .collect::<Vec<_>>();
-        let random_data = generate_datas(data_length, 1);
-        inject_qrom_datas(targets.clone(), controls, random_data);
-
-        let mut qubits = Vec::new();
-        qubits.extend(datas);
-        qubits.extend(ancillas);
-        qubits.extend(targets);
-        qubits.push(first_qubit);
+        let first_qubit = circuit.add_qubit("first").unwrap();
+        let first_control = circuit.control(first_qubit);
+        let controls = in_over_2n(&mut circuit, n, &first_control, &datas, &ancillas).unwrap();
+        let data_length = controls.len();
+        let random_data = generate_datas(data_length, 1, 1.0, &mut rng_from_seed(Some(0)));
+        inject_qrom_datas(&mut circuit, &targets, &controls, &random_data).unwrap();
 
-        // println!("{:#?}", qubits);
+        // println!("{:#?}", circuit);
 
-        let qasm_file = to_qasm(qubits);
+        let qasm_file = to_qasm(&circuit);
         println!("{}", qasm_file.to_string());
     }
 }
@@ -0,0 +1,271 @@
+// OpenQASM 3への書き出し
+// Circuit.registersをそのまま名前付きregisterとして宣言する
+use super::operations::{self, Operation};
+use super::to_qasm::to_qasm;
+use crate::primitive;
+use std::fmt;
+
+// QASM 3の予約語はregister名に使えない
+const RESERVED_WORDS: &[&str] = &[
+    "OPENQASM", "include", "def", "defcal", "cal", "gate", "extern", "box", "let", "break",
+    "continue", "if", "else", "end", "return", "for", "while", "in", "switch", "case", "default",
+    "input", "output", "const", "readonly", "mutable", "qreg", "qubit", "creg", "bool", "bit",
+    "int", "uint", "float", "angle", "complex", "array", "void", "duration", "stretch", "gphase",
+    "inv", "pow", "ctrl", "negctrl", "delay", "reset", "measure", "barrier", "true", "false", "pi",
+    "tau", "euler", "U",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Register {
+    pub name: String,
+    pub size: usize,
+    // falseの場合は`qubit name;`として宣言する
+    pub indexed: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct File {
+    pub registers: Vec<Register>,
+    // QubitIdごとの(registerの番号, register内のindex)
+    pub locations: Vec<(usize, usize)>,
+    // 古典bitは1つのbit registerにまとめる
+    pub clbit_register: String,
+    pub clbit_count: usize,
+    pub operations: Vec<Operation>,
+}
+
+// identifierとして使えない文字を置き換える
+fn sanitize(name: &str) -> String {
+    let mut sanitized = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    if !sanitized.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        sanitized.insert(0, '_');
+    }
+    if RESERVED_WORDS.contains(&sanitized.as_str()) {
+        sanitized.push('_');
+    }
+    sanitized
+}
+
+// 既に宣言したregisterと名前が被らないようにする
+fn unique_name(registers: &[Register], name: &str) -> String {
+    let mut name = sanitize(name);
+    while registers.iter().any(|register| register.name == name) {
+        name.push('_');
+    }
+    name
+}
+
+impl File {
+    fn qubit(&self, qubit_id: &operations::QubitId) -> String {
+        let (register_index, index) = self.locations[qubit_id.0 as usize];
+        let register = &self.registers[register_index];
+        if register.indexed {
+            format!("{}[{}]", register.name, index)
+        } else {
+            register.name.clone()
+        }
+    }
+    fn operation_to_string(&self, operation: &Operation) -> String {
+        match operation {
+            Operation::Z(target) => format!("z {};", self.qubit(target)),
+            Operation::H(target) => format!("h {};", self.qubit(target)),
+            Operation::X(target) => format!("x {};", self.qubit(target)),
+            Operation::T(target) => format!("t {};", self.qubit(target)),
+            Operation::TDag(target) => format!("tdg {};", self.qubit(target)),
+            Operation::S(target) => format!("s {};", self.qubit(target)),
+            Operation::SDag(target) => format!("sdg {};", self.qubit(target)),
+            Operation::Rz(angle, target) => format!("rz({}) {};", angle, self.qubit(target)),
+            Operation::Rx(angle, target) => format!("rx({}) {};", angle, self.qubit(target)),
+            Operation::Ry(angle, target) => format!("ry({}) {};", angle, self.qubit(target)),
+            Operation::U(theta, phi, lambda, target) => {
+                format!("u3({}, {}, {}) {};", theta, phi, lambda, self.qubit(target))
+            }
+            Operation::CX(from, to) => format!("cx {}, {};", self.qubit(from), self.qubit(to)),
+            Operation::Measure(target, clbit) => format!(
+                "{}[{}] = measure {};",
+                self.clbit_register,
+                clbit.0,
+                self.qubit(target)
+            ),
+            Operation::Reset(target) => format!("reset {};", self.qubit(target)),
+            Operation::If(clbit, gate, target) => format!(
+                "if ({}[{}]) {{ {} }}",
+                self.clbit_register,
+                clbit.0,
+                self.operation_to_string(&Operation::gate(*gate, *target))
+            ),
+        }
+    }
+}
+
+impl fmt::Display for File {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let header = "OPENQASM 3.0;\n";
+        let includer = "include \"stdgates.inc\";\n";
+        let qubit_declaration = self
+            .registers
+            .iter()
+            .map(|register| {
+                if register.indexed {
+                    format!("qubit[{}] {};\n", register.size, register.name)
+                } else {
+                    format!("qubit {};\n", register.name)
+                }
+            })
+            .collect::<String>();
+        let clbit_declaration = if self.clbit_count > 0 {
+            format!("bit[{}] {};\n", self.clbit_count, self.clbit_register)
+        } else {
+            "".to_string()
+        };
+        let qasm_strings = self
+            .operations
+            .iter()
+            .map(|operation| self.operation_to_string(operation))
+            .collect::<Vec<_>>()
+            .join("\n");
+        write!(
+            f,
+            "{}{}{}{}{}",
+            header, includer, qubit_declaration, clbit_declaration, qasm_strings
+        )
+    }
+}
+
+// registerは最初のqubitの位置で宣言するので、宣言した順に並ぶ
+// registerに属さないqubitは`qubit name;`として宣言する
+pub fn to_qasm3(circuit: &primitive::Circuit) -> File {
+    // QubitIdごとの(Circuit.registersの番号, register内のindex)
+    let mut owners = vec![None; circuit.qubits.len()];
+    circuit
+        .registers
+        .iter()
+        .enumerate()
+        .for_each(|(owner, register)| {
+            register
+                .qubits
+                .iter()
+                .enumerate()
+                .for_each(|(index, qubit)| owners[qubit.0] = Some((owner, index)))
+        });
+    let mut registers: Vec<Register> = Vec::new();
+    // Circuit.registersの番号ごとの、宣言したregisterの番号
+    let mut declared = vec![None; circuit.registers.len()];
+    let mut locations = Vec::with_capacity(circuit.qubits.len());
+    for (qubit, owner) in circuit.qubits.iter().zip(owners) {
+        let location = match owner {
+            Some((owner, index)) => {
+                let register_index = match declared[owner] {
+                    Some(register_index) => register_index,
+                    None => {
+                        let register = &circuit.registers[owner];
+                        registers.push(Register {
+                            name: unique_name(&registers, &register.name),
+                            size: register.qubits.len(),
+                            indexed: true,
+                        });
+                        declared[owner] = Some(registers.len() - 1);
+                        registers.len() - 1
+                    }
+                };
+                (register_index, index)
+            }
+            None => {
+                registers.push(Register {
+                    name: unique_name(&registers, &qubit.id),
+                    size: 1,
+                    indexed: false,
+                });
+                (registers.len() - 1, 0)
+            }
+        };
+        locations.push(location);
+    }
+    // qubitのregisterと名前が被らないようにする
+    let mut clbit_register = "c".to_string();
+    while registers
+        .iter()
+        .any(|register| register.name == clbit_register)
+    {
+        clbit_register.push('_');
+    }
+    let file = to_qasm(circuit);
+    File {
+        registers,
+        locations,
+        clbit_register,
+        clbit_count: file.clbit_count,
+        operations: file.operations,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gates::cnot;
+    use crate::primitive::{Circuit, PrimitiveGate};
+    #[test]
+    fn named_registers() {
+        let mut circuit = Circuit::new();
+        let data = circuit.register("data", 2).unwrap();
+        let input = circuit.register("input", 1).unwrap();
+        let first = circuit.add_qubit("first").unwrap();
+        circuit.gate(data[1], PrimitiveGate::H);
+        cnot(&mut circuit, first, data[0]).unwrap();
+        circuit.gate(input[0], PrimitiveGate::TDag);
+        let file = to_qasm3(&circuit);
+        let qasm_string = "OPENQASM 3.0;\n".to_string()
+            + "include \"stdgates.inc\";\n"
+            + "qubit[2] data;\n"
+            + "qubit[1] input_;\n"
+            + "qubit first;\n"
+            + "h data[1];\n"
+            + "tdg input_[0];\n"
+            + "cx first, data[0];";
+        assert_eq!(file.to_string(), qasm_string);
+    }
+    #[test]
+    fn classical() {
+        let mut circuit = Circuit::new();
+        let data = circuit.register("data", 1).unwrap()[0];
+        let c = circuit.add_qubit("c").unwrap();
+        let clbit = circuit.add_clbit("m");
+        circuit.measure(data, clbit);
+        circuit.conditioned_gate(c, clbit, PrimitiveGate::Z);
+        circuit.reset(data);
+        let file = to_qasm3(&circuit);
+        let qasm_string = "OPENQASM 3.0;\n".to_string()
+            + "include \"stdgates.inc\";\n"
+            + "qubit[1] data;\n"
+            + "qubit c;\n"
+            + "bit[1] c_;\n"
+            + "c_[0] = measure data[0];\n"
+            + "reset data[0];\n"
+            + "if (c_[0]) { z c; }";
+        assert_eq!(file.to_string(), qasm_string);
+    }
+    #[test]
+    fn declared_registers_only() {
+        // 名前が"_数字"で終わっていてもregisterに属さないqubitはまとめない
+        let mut circuit = Circuit::new();
+        let target = circuit.register("target", 1).unwrap();
+        let loose = circuit.add_qubit("ancilla_0").unwrap();
+        let data = circuit.register("data", 1).unwrap();
+        circuit.gate(target[0], PrimitiveGate::X);
+        circuit.gate(loose, PrimitiveGate::H);
+        circuit.gate(data[0], PrimitiveGate::Z);
+        let qasm_string = "OPENQASM 3.0;\n".to_string()
+            + "include \"stdgates.inc\";\n"
+            + "qubit[1] target;\n"
+            + "qubit ancilla_0;\n"
+            + "qubit[1] data;\n"
+            + "x target[0];\n"
+            + "h ancilla_0;\n"
+            + "z data[0];";
+        assert_eq!(to_qasm3(&circuit).to_string(), qasm_string);
+        assert_eq!(sanitize("1-a"), "_1_a");
+    }
+}
@@ -1,2 +1,3 @@
 pub mod data_combine;
+pub mod layered;
 pub mod simple_select_controls;
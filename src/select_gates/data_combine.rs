@@ -1,46 +1,183 @@
+use crate::error::{self, Error};
 use crate::primitive::*;
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::fmt;
 
-// cyclomatic complexity: 1 + 3(loop) = 4
-pub fn inject_qrom_datas(qcs: Vec<QubitCell>, cts: Vec<ControlFrom>, data_list: Vec<Vec<bool>>) {
-    data_list.iter().enumerate().for_each(|(index, data)| {
+// QROMに読み込むデータテーブルの検証エラー
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DataTableError {
+    // 行数がcontrolの数と一致しない
+    RowCount {
+        expected: usize,
+        actual: usize,
+    },
+    // 行の幅がtarget registerの幅と一致しない
+    Width {
+        row: usize,
+        expected: usize,
+        actual: usize,
+    },
+    // wordがwidth bitに収まらない
+    WordOverflow {
+        row: usize,
+        word: u64,
+        width: usize,
+    },
+}
+
+impl fmt::Display for DataTableError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DataTableError::RowCount { expected, actual } => write!(
+                f,
+                "data table has {} rows but the select produces {} controls",
+                actual, expected
+            ),
+            DataTableError::Width {
+                row,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "row {} has width {} but the target register has {} qubits",
+                row, actual, expected
+            ),
+            DataTableError::WordOverflow { row, word, width } => {
+                write!(
+                    f,
+                    "word {} in row {} does not fit in {} bits",
+                    word, row, width
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for DataTableError {}
+
+// 行数とtarget幅を検査する
+pub fn validate_datas(
+    data_list: &[Vec<bool>],
+    rows: usize,
+    width: usize,
+) -> Result<(), DataTableError> {
+    if data_list.len() != rows {
+        return Err(DataTableError::RowCount {
+            expected: rows,
+            actual: data_list.len(),
+        });
+    }
+    match data_list
+        .iter()
+        .enumerate()
+        .find(|(_, data)| data.len() != width)
+    {
+        Some((row, data)) => Err(DataTableError::Width {
+            row,
+            expected: width,
+            actual: data.len(),
+        }),
+        None => Ok(()),
+    }
+}
+
+// 整数のwordをwidth bitのテーブルに変換する
+// bit jがtarget_jに対応する(little endian)
+pub fn words_to_datas(words: &[u64], width: usize) -> Result<Vec<Vec<bool>>, DataTableError> {
+    words
+        .iter()
+        .enumerate()
+        .map(|(row, word)| {
+            if width < 64 && *word >> width != 0 {
+                return Err(DataTableError::WordOverflow {
+                    row,
+                    word: *word,
+                    width,
+                });
+            }
+            Ok((0..width)
+                .map(|bit| bit < 64 && (word >> bit) & 1 == 1)
+                .collect())
+        })
+        .collect()
+}
+
+// cyclomatic complexity: 1 + 2(condition) + 3(loop) = 6
+pub fn inject_qrom_datas(
+    circuit: &mut Circuit,
+    qcs: &[QubitId],
+    cts: &[ControlFrom],
+    data_list: &[Vec<bool>],
+) -> error::Result<()> {
+    if data_list.len() > cts.len() {
+        return Err(Error::invalid_argument(format!(
+            "{} rows of data but only {} controls",
+            data_list.len(),
+            cts.len()
+        )));
+    }
+    if data_list.iter().any(|data| data.len() > qcs.len()) {
+        return Err(Error::invalid_argument(format!(
+            "data is wider than the {} target qubits",
+            qcs.len()
+        )));
+    }
+    data_list.iter().enumerate().try_for_each(|(index, data)| {
         let control = &cts[index];
-        data.iter().enumerate().for_each(|(target, value)| {
+        data.iter().enumerate().try_for_each(|(target, value)| {
             if *value {
-                let export = Qubit::export(qcs[target].clone());
-                export.control_by(control);
+                let export = circuit.export(qcs[target]);
+                export.control_by(circuit, control)?;
             }
-        });
-    });
+            Ok(())
+        })
+    })
 }
 
+// seedがあれば再現可能なrng、なければentropyから作る
+// StdRngはrandのversionで値が変わりうるので、algorithmを固定したChaCha8を使う
+pub fn rng_from_seed(seed: Option<u64>) -> ChaCha8Rng {
+    match seed {
+        Some(seed) => ChaCha8Rng::seed_from_u64(seed),
+        None => ChaCha8Rng::from_entropy(),
+    }
+}
+
+// 各bitはdensityの確率でtrueになる
 // cyclomatic complexity: 1 + 2(loop) = 3
-pub fn generate_datas(count: usize, length: usize) -> Vec<Vec<bool>> {
-    // use rand::Rng;
-    // let mut rng = rand::thread_rng();
+pub fn generate_datas<R: Rng>(
+    count: usize,
+    length: usize,
+    density: f64,
+    rng: &mut R,
+) -> Vec<Vec<bool>> {
     let mut ret = Vec::new();
     for _ in 0..count {
         let mut inner_vec = Vec::new();
         for _ in 0..length {
-            // let rng_value = rng.gen::<bool>();
-            // inner_vec.push(rng_value);
-            inner_vec = vec![true; length]
+            inner_vec.push(rng.gen_bool(density));
         }
         ret.push(inner_vec)
     }
     ret
 }
 
-pub fn combine_random_cnots_m_interaction(
+pub fn combine_random_cnots_m_interaction<R: Rng>(
+    circuit: &mut Circuit,
     m: i32,
-    control_froms: Vec<ControlFrom>,
-    qubits: Vec<QubitCell>,
-) -> Vec<QubitCell> {
-    assert!(m > 0, "m must be positive");
-    assert!(
-        m <= control_froms.len() as i32,
-        "m must be less than control_froms length"
-    );
+    control_froms: &[ControlFrom],
+    qubits: &[QubitId],
+    rng: &mut R,
+) -> error::Result<Vec<QubitId>> {
+    if m <= 0 {
+        return Err(Error::invalid_argument("m must be positive"));
+    }
+    if m > control_froms.len() as i32 {
+        return Err(Error::invalid_argument(
+            "m must be less than control_froms length",
+        ));
+    }
     // for each qubits iterate...
     qubits
         .iter()
@@ -48,7 +185,7 @@ pub fn combine_random_cnots_m_interaction(
             // pick up m random control_from index from control_froms
             let mut picked_control_froms_idx = vec![];
             while picked_control_froms_idx.len() < m as usize {
-                let idx = rand::thread_rng().gen_range(0..control_froms.len());
+                let idx = rng.gen_range(0..control_froms.len());
                 if !picked_control_froms_idx.contains(&idx) {
                     picked_control_froms_idx.push(idx);
                 }
@@ -58,14 +195,85 @@ pub fn combine_random_cnots_m_interaction(
             // pick up control_from from control_froms by index in picked_control_froms_idx
             let picked_control_froms = picked_control_froms_idx
                 .iter()
-                .map(|idx| control_froms[*idx].clone())
+                .map(|idx| control_froms[*idx])
                 .collect::<Vec<_>>();
             // apply control_from to qubit
-            picked_control_froms.iter().for_each(|control_from| {
-                let control_target = Qubit::export(qubit.clone());
-                control_target.control_by(control_from);
-            });
-            qubit.clone()
+            picked_control_froms.iter().try_for_each(|control_from| {
+                let control_target = circuit.export(*qubit);
+                control_target.control_by(circuit, control_from)
+            })?;
+            Ok(*qubit)
         })
-        .collect::<Vec<_>>()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn same_seed_same_datas() {
+        let first = generate_datas(16, 3, 0.5, &mut rng_from_seed(Some(7)));
+        let second = generate_datas(16, 3, 0.5, &mut rng_from_seed(Some(7)));
+        assert_eq!(first, second);
+    }
+    #[test]
+    fn seeded_datas_are_pinned() {
+        // randのversionが変わっても同じseedなら同じテーブルになる
+        let bits = generate_datas(1, 16, 0.5, &mut rng_from_seed(Some(42)))[0]
+            .iter()
+            .map(|bit| if *bit { '1' } else { '0' })
+            .collect::<String>();
+        assert_eq!(bits, "0010111001000001");
+    }
+    #[test]
+    fn density_bounds() {
+        let mut rng = rng_from_seed(Some(0));
+        assert!(generate_datas(8, 2, 1.0, &mut rng)
+            .iter()
+            .all(|row| row.iter().all(|bit| *bit)));
+        assert!(generate_datas(8, 2, 0.0, &mut rng)
+            .iter()
+            .all(|row| row.iter().all(|bit| !*bit)));
+    }
+    #[test]
+    fn words_little_endian() {
+        let datas = words_to_datas(&[0b01, 0b10, 0b11], 2).unwrap();
+        assert_eq!(
+            datas,
+            vec![vec![true, false], vec![false, true], vec![true, true]]
+        );
+    }
+    #[test]
+    fn words_overflow() {
+        assert_eq!(
+            words_to_datas(&[1, 4], 2),
+            Err(DataTableError::WordOverflow {
+                row: 1,
+                word: 4,
+                width: 2
+            })
+        );
+    }
+    #[test]
+    fn validate_rows_and_width() {
+        let datas = vec![vec![true, false]; 4];
+        assert_eq!(validate_datas(&datas, 4, 2), Ok(()));
+        assert_eq!(
+            validate_datas(&datas, 8, 2),
+            Err(DataTableError::RowCount {
+                expected: 8,
+                actual: 4
+            })
+        );
+        let mut ragged = datas;
+        ragged[2].push(true);
+        assert_eq!(
+            validate_datas(&ragged, 4, 2),
+            Err(DataTableError::Width {
+                row: 2,
+                expected: 2,
+                actual: 3
+            })
+        );
+    }
 }
@@ -0,0 +1,403 @@
+use crate::error::{Error, Result};
+use crate::primitive::*;
+use crate::select_gates::data_combine::*;
+use crate::select_gates::simple_select_controls::*;
+
+// threadsがNoneなら使えるcoreの数だけ使う
+pub fn thread_count(threads: Option<usize>) -> Result<usize> {
+    match threads {
+        Some(0) => Err(Error::invalid_argument("threads must be positive")),
+        Some(threads) => Ok(threads),
+        None => Ok(std::thread::available_parallelism()
+            .map(|threads| threads.get())
+            .unwrap_or(1)),
+    }
+}
+
+// itemsをthreads個に分けてfを並列にかける。結果はitemsの順に並ぶ
+// 回路はindexで繋がったarenaなのでthreadをまたいで返せる
+pub fn parallel_map<T: Sync, R: Send>(
+    items: &[T],
+    threads: usize,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    if threads <= 1 || items.len() <= 1 {
+        return items.iter().map(f).collect();
+    }
+    let f = &f;
+    std::thread::scope(|scope| {
+        let handles = items
+            .chunks(items.len().div_ceil(threads))
+            .map(|chunk| scope.spawn(move || chunk.iter().map(f).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .flat_map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            })
+            .collect()
+    })
+}
+
+// SELECTが作るcontrolの数。lengthがNoneなら2^n
+pub fn select_length(n: i32, length: Option<usize>) -> Result<usize> {
+    match length {
+        Some(length) => Ok(length),
+        None => 1usize
+            .checked_shl(n.max(0) as u32)
+            .ok_or_else(|| Error::invalid_argument(format!("n is too large, got {}", n))),
+    }
+}
+
+// cyclomatic complexity: 1 + 4(loop) + 3-1(generate_datas) + 8-1(in_over_2n) + 4-1(inject_qrom_datas) = 17
+pub fn uniform_layered_internal(
+    n: i32,
+    count: i32,
+    length: Option<usize>,
+    density: f64,
+    seed: Option<u64>,
+    uncompute: Uncompute,
+    threads: usize,
+) -> Result<Vec<Circuit>> {
+    // 乱数は1つのrngから回路の順に引くので、threadの数によらず同じseedなら同じ回路になる
+    let mut rng = rng_from_seed(seed);
+    let data_length = select_length(n, length)?;
+    let target_datas = (0..count)
+        .map(|_| generate_datas(data_length, 1, density, &mut rng))
+        .collect::<Vec<_>>();
+    parallel_map(&target_datas, threads, |target_data| {
+        // qubitは追加した順(data, ancilla, target, first)に出力される
+        let mut circuit = Circuit::new();
+        let datas = circuit.register("data", n.max(0) as usize)?;
+        let ancillas = circuit.register("ancilla", n.max(0) as usize)?;
+        let targets = circuit.register("target", n.max(0) as usize)?;
+        let first_qubit = circuit.add_qubit("first")?;
+        let first_control = circuit.control(first_qubit);
+        let controls = select_controls(
+            &mut circuit,
+            n,
+            length,
+            &first_control,
+            &datas,
+            &ancillas,
+            uncompute,
+        )?;
+        inject_qrom_datas(&mut circuit, &targets, &controls, target_data)?;
+        Ok(circuit)
+    })
+    .into_iter()
+    .collect()
+}
+
+pub fn uniform_layered_internal_redundant(
+    n: i32,
+    count: i32,
+    r: i32,
+    density: f64,
+    seed: Option<u64>,
+) -> Result<Vec<Circuit>> {
+    let mut rng = rng_from_seed(seed);
+    let mut circuits = Vec::new();
+    for _ in 0..count {
+        let mut circuit = Circuit::new();
+        let datas = circuit.register("data", n.max(0) as usize)?;
+        let ancillas = circuit.register("ancilla", n.max(0) as usize)?;
+        let targets = circuit.register("target", n.max(0) as usize)?;
+        for i in n..(n + r) {
+            let qc = circuit.add_qubit(format!("redundant_target_{}", i).as_str())?;
+            circuit.gate(qc, PrimitiveGate::X);
+            circuit.gate(qc, PrimitiveGate::X);
+        }
+        let first_qubit = circuit.add_qubit("first")?;
+        let first_control = circuit.control(first_qubit);
+        let controls = in_over_2n(&mut circuit, n, &first_control, &datas, &ancillas)?;
+        let data_length = controls.len();
+        // let target_length = targets.len();
+        let random_data = generate_datas(data_length, 1, density, &mut rng);
+        inject_qrom_datas(&mut circuit, &targets, &controls, &random_data)?;
+
+        circuits.push(circuit);
+    }
+    Ok(circuits)
+}
+
+// 最初のcontrolだけをtarget_s1につないだSELECT
+pub fn layered_internal(n: i32) -> Result<Circuit> {
+    let mut circuit = Circuit::new();
+    let datas = circuit.register("data", n.max(0) as usize)?;
+    let ancillas = circuit.register("ancilla", n.max(0) as usize)?;
+    let first_qubit = circuit.add_qubit("first")?;
+    let target_sample_1 = circuit.add_qubit("target_s1")?;
+    let first_control = circuit.control(first_qubit);
+    let controls = in_over_2n(&mut circuit, n, &first_control, &datas, &ancillas)?;
+    let export_target_sample_1 = circuit.export(target_sample_1);
+    export_target_sample_1.control_by(&mut circuit, &controls[0])?;
+    Ok(circuit)
+}
+
+// 呼び出し側が与えたテーブルを読み込む
+// widthはtarget registerの幅で、各行の長さと一致している必要がある
+// lengthがSomeの場合、テーブルの行数はlengthと一致している必要がある
+// 2^nの木を作る前にテーブルを検査するので、大きなnでも行数が違えばすぐに失敗する
+pub fn uniform_layered_internal_with_data(
+    n: i32,
+    length: Option<usize>,
+    width: usize,
+    target_data: &[Vec<bool>],
+    uncompute: Uncompute,
+) -> Result<Circuit> {
+    validate_datas(target_data, select_length(n, length)?, width)?;
+    let mut circuit = Circuit::new();
+    let datas = circuit.register("data", n.max(0) as usize)?;
+    let ancillas = circuit.register("ancilla", n.max(0) as usize)?;
+    let targets = circuit.register("target", width)?;
+    let first_qubit = circuit.add_qubit("first")?;
+    let first_control = circuit.control(first_qubit);
+    let controls = select_controls(
+        &mut circuit,
+        n,
+        length,
+        &first_control,
+        &datas,
+        &ancillas,
+        uncompute,
+    )?;
+    inject_qrom_datas(&mut circuit, &targets, &controls, target_data)?;
+    Ok(circuit)
+}
+
+// pythonから渡されたdata/words/widthを(width, テーブル)にまとめる
+// どちらも渡されなかった場合はNone
+// widthを省略した場合、dataは最初の行の幅、wordsはn bitになる
+pub fn resolve_data_table(
+    n: i32,
+    data: Option<Vec<Vec<bool>>>,
+    words: Option<Vec<u64>>,
+    width: Option<usize>,
+) -> Result<Option<(usize, Vec<Vec<bool>>)>> {
+    match (data, words) {
+        (Some(_), Some(_)) => Err(Error::invalid_argument(
+            "data and words cannot be given at the same time",
+        )),
+        (Some(data), None) => {
+            let width = width.unwrap_or_else(|| data.first().map_or(0, |row| row.len()));
+            Ok(Some((width, data)))
+        }
+        (None, Some(words)) => {
+            let width = width.unwrap_or(n.max(0) as usize);
+            words_to_datas(&words, width)
+                .map(|data| Some((width, data)))
+                .map_err(Error::from)
+        }
+        (None, None) => Ok(None),
+    }
+}
+
+// lengthは1以上2^n以下である必要がある
+pub fn check_length(n: i32, length: Option<usize>) -> Result<()> {
+    match length {
+        Some(length) if n <= 0 || length == 0 || length > select_length(n, None)? => Err(
+            Error::invalid_argument(format!("length must be between 1 and 2^n, got {}", length)),
+        ),
+        _ => Ok(()),
+    }
+}
+
+// densityは確率なので[0, 1]に収まっている必要がある
+pub fn check_density(density: f64) -> Result<()> {
+    if (0.0..=1.0).contains(&density) {
+        Ok(())
+    } else {
+        Err(Error::invalid_argument(format!(
+            "density must be between 0 and 1, got {}",
+            density
+        )))
+    }
+}
+
+// uncomputeは"toffoli"か"measurement"
+pub fn parse_uncompute(uncompute: &str) -> Result<Uncompute> {
+    match uncompute {
+        "toffoli" => Ok(Uncompute::Toffoli),
+        "measurement" => Ok(Uncompute::Measurement),
+        _ => Err(Error::invalid_argument(format!(
+            "uncompute must be \"toffoli\" or \"measurement\", got {:?}",
+            uncompute
+        ))),
+    }
+}
+
+// テーブルが与えられた場合はそれを、なければランダムなデータでcount個生成する
+#[allow(clippy::too_many_arguments)]
+pub fn uniform_layered_internal_resolved(
+    n: i32,
+    count: i32,
+    length: Option<usize>,
+    table: Option<(usize, Vec<Vec<bool>>)>,
+    density: f64,
+    seed: Option<u64>,
+    uncompute: Uncompute,
+    threads: usize,
+) -> Result<Vec<Circuit>> {
+    check_length(n, length)?;
+    match table {
+        None => {
+            check_density(density)?;
+            uniform_layered_internal(n, count, length, density, seed, uncompute, threads)
+        }
+        Some((width, data)) => {
+            let indices = (0..count).collect::<Vec<_>>();
+            parallel_map(&indices, threads, |_| {
+                uniform_layered_internal_with_data(n, length, width, &data, uncompute)
+            })
+            .into_iter()
+            .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qasm::operations::{Operation, QubitId};
+    #[test]
+    fn with_data_loads_table() {
+        let n = 2;
+        let table = vec![
+            vec![true, false, false],
+            vec![false, true, false],
+            vec![true, true, false],
+            vec![false, false, true],
+        ];
+        let circuit =
+            uniform_layered_internal_with_data(n, None, 3, &table, Uncompute::Toffoli).unwrap();
+        let file = circuit.to_qasm();
+        // data 2, ancilla 2の後ろにtarget 3がある
+        let cx_into = |target: i32| {
+            file.operations
+                .iter()
+                .filter(|op| matches!(op, Operation::CX(_, to) if *to == QubitId(target)))
+                .count()
+        };
+        assert_eq!(cx_into(4), 2);
+        assert_eq!(cx_into(5), 2);
+        assert_eq!(cx_into(6), 1);
+    }
+    #[test]
+    fn with_data_over_range() {
+        let table = vec![vec![true]; 5];
+        let circuit =
+            uniform_layered_internal_with_data(3, Some(5), 1, &table, Uncompute::Toffoli).unwrap();
+        let target_cx = circuit
+            .to_qasm()
+            .operations
+            .iter()
+            .filter(|op| matches!(op, Operation::CX(_, to) if *to == QubitId(6)))
+            .count();
+        assert_eq!(target_cx, 5);
+        assert!(
+            uniform_layered_internal_with_data(3, None, 1, &table, Uncompute::Toffoli).is_err()
+        );
+    }
+    #[test]
+    fn same_seed_same_qasm() {
+        let qasms = |seed| {
+            uniform_layered_internal(3, 2, None, 0.5, Some(seed), Uncompute::Toffoli, 1)
+                .unwrap()
+                .into_iter()
+                .map(|circuit| circuit.to_qasm().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(qasms(42), qasms(42));
+    }
+    #[test]
+    fn with_data_rejects_mismatch() {
+        let table = vec![vec![true]; 3];
+        assert_eq!(
+            uniform_layered_internal_with_data(2, None, 1, &table, Uncompute::Toffoli).err(),
+            Some(Error::DataTable(DataTableError::RowCount {
+                expected: 4,
+                actual: 3
+            }))
+        );
+        let table = vec![vec![true]; 4];
+        assert!(
+            uniform_layered_internal_with_data(2, None, 2, &table, Uncompute::Toffoli).is_err()
+        );
+        // 木を作る前に失敗する
+        let table = vec![vec![true]; 2];
+        assert_eq!(
+            uniform_layered_internal_with_data(40, None, 1, &table, Uncompute::Toffoli).err(),
+            Some(Error::DataTable(DataTableError::RowCount {
+                expected: 1 << 40,
+                actual: 2
+            }))
+        );
+        assert!(matches!(
+            uniform_layered_internal_with_data(64, None, 1, &table, Uncompute::Toffoli),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+    #[test]
+    fn resolve_infers_width() {
+        let table = vec![vec![true, false, true]; 4];
+        assert_eq!(
+            resolve_data_table(2, Some(table.clone()), None, None).unwrap(),
+            Some((3, table))
+        );
+        assert_eq!(
+            resolve_data_table(2, None, Some(vec![1, 2]), None).unwrap(),
+            Some((2, vec![vec![true, false], vec![false, true]]))
+        );
+        assert!(resolve_data_table(2, Some(vec![]), Some(vec![]), None).is_err());
+    }
+    #[test]
+    fn threads_keep_order() {
+        let qasms = |threads| {
+            uniform_layered_internal(3, 5, Some(6), 0.5, Some(1), Uncompute::Toffoli, threads)
+                .unwrap()
+                .into_iter()
+                .map(|circuit| circuit.to_qasm().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(qasms(1), qasms(3));
+        assert_eq!(
+            parallel_map(&[1, 2, 3, 4, 5], 2, |x| x * 10),
+            vec![10, 20, 30, 40, 50]
+        );
+        assert!(thread_count(Some(0)).is_err());
+        assert!(thread_count(None).unwrap() >= 1);
+    }
+    #[test]
+    fn resolved_rejects_bad_arguments() {
+        let resolved = |length, density| {
+            uniform_layered_internal_resolved(
+                2,
+                1,
+                length,
+                None,
+                density,
+                Some(0),
+                Uncompute::Toffoli,
+                2,
+            )
+            .err()
+        };
+        assert!(matches!(
+            resolved(Some(5), 0.5),
+            Some(Error::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            resolved(None, 1.5),
+            Some(Error::InvalidArgument(_))
+        ));
+        assert_eq!(resolved(Some(3), 0.5), None);
+        assert!(matches!(
+            check_length(64, Some(3)),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert_eq!(check_length(63, Some(3)), Ok(()));
+    }
+}
@@ -1,73 +1,331 @@
+use crate::error::{Error, Result};
 use crate::gates::*;
 use crate::primitive::*;
 
-// cyclomatic complexity: 1
+// in_layerでoutputを|0>に戻す方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Uncompute {
+    // Toffoliの逆回路で戻す(in_layer 1回につきT 8個)
+    Toffoli,
+    // X基底の測定と古典制御のCZで戻す(in_layer 1回につきT 4個)
+    Measurement,
+}
+
+// 左はdataが0、右はdataが1の場合のcontrolになる
+// outputは|0>から始まり、最後に|0>に戻る
 pub fn in_layer(
+    circuit: &mut Circuit,
     q1c: &ControlFrom,
-    data: QubitCell,
-    output: QubitCell,
-) -> (ControlFrom, ControlFrom) {
-    toffoli_first_control(q1c, data.clone(), output.clone());
+    data: QubitId,
+    output: QubitId,
+) -> Result<(ControlFrom, ControlFrom)> {
+    in_layer_with(circuit, q1c, data, output, Uncompute::Toffoli)
+}
 
-    let control_left = Qubit::control(output.clone());
+// cyclomatic complexity: 2
+pub fn in_layer_with(
+    circuit: &mut Circuit,
+    q1c: &ControlFrom,
+    data: QubitId,
+    output: QubitId,
+    uncompute: Uncompute,
+) -> Result<(ControlFrom, ControlFrom)> {
+    // output = q1 and not data
+    circuit.gate(data, PrimitiveGate::X);
+    logical_and_first_control(circuit, q1c, data, output)?;
+    circuit.gate(data, PrimitiveGate::X);
 
-    let export_1 = Qubit::export(output.clone());
-    export_1.control_by(q1c);
+    let control_left = circuit.control(output);
 
-    let control_right = Qubit::control(output.clone());
+    // output = q1 and data
+    let export = circuit.export(output);
+    export.control_by(circuit, q1c)?;
 
-    let export_2 = Qubit::export(output.clone());
-    export_2.control_by(q1c);
+    let control_right = circuit.control(output);
 
-    Qubit::gate(data.clone(), PrimitiveGate::X);
-    toffoli_first_control(q1c, data.clone(), output);
-    Qubit::gate(data, PrimitiveGate::X);
+    match uncompute {
+        Uncompute::Toffoli => toffoli_first_control_dag(circuit, q1c, data, output),
+        Uncompute::Measurement => logical_and_uncompute_first_control(circuit, q1c, data, output),
+    }?;
 
-    (control_left, control_right)
+    Ok((control_left, control_right))
 }
 
-// cyclomatic complexity: 1 + 5(condition) + 2(recursion)= 8(no increase by subsequent call)
 pub fn in_over_2n(
+    circuit: &mut Circuit,
     n: i32,
     control: &ControlFrom,
-    datas: Vec<QubitCell>,
-    ancillas: Vec<QubitCell>,
-) -> Vec<ControlFrom> {
+    datas: &[QubitId],
+    ancillas: &[QubitId],
+) -> Result<Vec<ControlFrom>> {
+    in_over_2n_with(circuit, n, control, datas, ancillas, Uncompute::Toffoli)
+}
+
+// cyclomatic complexity: 1 + 5(condition) + 2(recursion)= 8(no increase by subsequent call)
+pub fn in_over_2n_with(
+    circuit: &mut Circuit,
+    n: i32,
+    control: &ControlFrom,
+    datas: &[QubitId],
+    ancillas: &[QubitId],
+    uncompute: Uncompute,
+) -> Result<Vec<ControlFrom>> {
     if datas.len() != ancillas.len() {
-        panic!("datas and ancillas must be the same length");
+        return Err(Error::invalid_argument(
+            "datas and ancillas must be the same length",
+        ));
     }
     if datas.len() != n as usize {
-        panic!("datas length and depth must be the same");
+        return Err(Error::invalid_argument(
+            "datas length and depth must be the same",
+        ));
     }
     if n <= 0 {
-        panic!("n must be greater than 0");
+        return Err(Error::invalid_argument("n must be greater than 0"));
     }
     if n == 1 {
-        let data = datas[0].clone();
-        let ancilla = ancillas[0].clone();
-        let controls_tuple = in_layer(control, data, ancilla);
-        vec![controls_tuple.0, controls_tuple.1]
+        let data = datas[0];
+        let ancilla = ancillas[0];
+        let controls_tuple = in_layer_with(circuit, control, data, ancilla, uncompute)?;
+        Ok(vec![controls_tuple.0, controls_tuple.1])
     } else {
-        let first_data = datas[0].clone();
-        let first_ancilla = ancillas[0].clone();
-        let remaining_datas = datas[1..].to_vec();
-        let remaining_ancillas = ancillas[1..].to_vec();
-        let controls_tuple = in_layer(control, first_data, first_ancilla);
+        let first_data = datas[0];
+        let first_ancilla = ancillas[0];
+        let remaining_datas = &datas[1..];
+        let remaining_ancillas = &ancillas[1..];
+        let controls_tuple = in_layer_with(circuit, control, first_data, first_ancilla, uncompute)?;
         let mut controls = Vec::new();
-        let left_controls = in_over_2n(
+        let left_controls = in_over_2n_with(
+            circuit,
             n - 1,
             &controls_tuple.0,
-            remaining_datas.clone(),
-            remaining_ancillas.clone(),
-        );
-        let right_controls = in_over_2n(
+            remaining_datas,
+            remaining_ancillas,
+            uncompute,
+        )?;
+        let right_controls = in_over_2n_with(
+            circuit,
             n - 1,
             &controls_tuple.1,
             remaining_datas,
             remaining_ancillas,
-        );
+            uncompute,
+        )?;
         controls.extend(left_controls);
         controls.extend(right_controls);
-        controls
+        Ok(controls)
+    }
+}
+
+// 2^nではなく任意の長さlengthのcontrolを作る
+// length-1以降の部分木は刈り取るので、in_layerの呼び出し(Toffoli)はlength-1回になる
+// cyclomatic complexity: 1 + 6(condition) + 2(recursion) = 9
+pub fn in_over_range(
+    circuit: &mut Circuit,
+    n: i32,
+    length: usize,
+    control: &ControlFrom,
+    datas: &[QubitId],
+    ancillas: &[QubitId],
+    uncompute: Uncompute,
+) -> Result<Vec<ControlFrom>> {
+    if datas.len() != ancillas.len() {
+        return Err(Error::invalid_argument(
+            "datas and ancillas must be the same length",
+        ));
+    }
+    if datas.len() != n as usize {
+        return Err(Error::invalid_argument(
+            "datas length and depth must be the same",
+        ));
+    }
+    let max_length = 1usize
+        .checked_shl(n as u32)
+        .ok_or_else(|| Error::invalid_argument(format!("n is too large, got {}", n)))?;
+    if length == 0 || length > max_length {
+        return Err(Error::invalid_argument("length must be between 1 and 2^n"));
+    }
+    if length == 1 {
+        // 残りのbitは見なくてよい
+        return Ok(vec![*control]);
+    }
+    let half = 1 << (n - 1);
+    let remaining_datas = &datas[1..];
+    let remaining_ancillas = &ancillas[1..];
+    if length <= half {
+        // 右の部分木は空なので、この段のbitは調べずに左へ降りる
+        return in_over_range(
+            circuit,
+            n - 1,
+            length,
+            control,
+            remaining_datas,
+            remaining_ancillas,
+            uncompute,
+        );
+    }
+    let controls_tuple = in_layer_with(circuit, control, datas[0], ancillas[0], uncompute)?;
+    if n == 1 {
+        return Ok(vec![controls_tuple.0, controls_tuple.1]);
+    }
+    let mut controls = in_over_2n_with(
+        circuit,
+        n - 1,
+        &controls_tuple.0,
+        remaining_datas,
+        remaining_ancillas,
+        uncompute,
+    )?;
+    controls.extend(in_over_range(
+        circuit,
+        n - 1,
+        length - half,
+        &controls_tuple.1,
+        remaining_datas,
+        remaining_ancillas,
+        uncompute,
+    )?);
+    Ok(controls)
+}
+
+// lengthがNoneなら2^n個、Someならその長さのcontrolを作る
+pub fn select_controls(
+    circuit: &mut Circuit,
+    n: i32,
+    length: Option<usize>,
+    control: &ControlFrom,
+    datas: &[QubitId],
+    ancillas: &[QubitId],
+    uncompute: Uncompute,
+) -> Result<Vec<ControlFrom>> {
+    match length {
+        None => in_over_2n_with(circuit, n, control, datas, ancillas, uncompute),
+        Some(length) => in_over_range(circuit, n, length, control, datas, ancillas, uncompute),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qasm::from_qasm::from_qasm;
+    use crate::qasm::operations::Operation;
+    use crate::qasm::to_qasm::to_qasm;
+    use crate::simulator::basis::simulate;
+    #[test]
+    fn range_prunes_toffolis() {
+        let n = 3;
+        (1..=(1 << n)).for_each(|length| {
+            let mut circuit = Circuit::new();
+            let first = circuit.add_qubit("first").unwrap();
+            let first_control = circuit.control(first);
+            let datas = (0..n)
+                .map(|i| circuit.add_qubit(&format!("data_{}", i)).unwrap())
+                .collect::<Vec<_>>();
+            let ancillas = (0..n)
+                .map(|i| circuit.add_qubit(&format!("ancilla_{}", i)).unwrap())
+                .collect::<Vec<_>>();
+            let target = circuit.add_qubit("target_0").unwrap();
+            let controls = in_over_range(
+                &mut circuit,
+                n,
+                length,
+                &first_control,
+                &datas,
+                &ancillas,
+                Uncompute::Toffoli,
+            )
+            .unwrap();
+            assert_eq!(controls.len(), length);
+            controls.iter().for_each(|control| {
+                circuit
+                    .export(target)
+                    .control_by(&mut circuit, control)
+                    .unwrap()
+            });
+            let t_count = to_qasm(&circuit)
+                .operations
+                .iter()
+                .filter(|op| matches!(op, Operation::T(_) | Operation::TDag(_)))
+                .count();
+            // in_layer 1回につき計算と逆計算でT 8個
+            assert_eq!(t_count, 8 * (length - 1));
+        });
+    }
+    #[test]
+    fn leaves_follow_address() {
+        // k番目のcontrolはaddress kのときだけ1になり、ancillaは|0>に戻る
+        let n = 2;
+        let mut circuit = Circuit::new();
+        let datas = circuit.register("data", n as usize).unwrap();
+        let ancillas = circuit.register("ancilla", n as usize).unwrap();
+        let targets = circuit.register("target", 1 << n).unwrap();
+        let first = circuit.add_qubit("first").unwrap();
+        let first_control = circuit.control(first);
+        let controls = in_over_2n(&mut circuit, n, &first_control, &datas, &ancillas).unwrap();
+        controls.iter().zip(&targets).for_each(|(control, target)| {
+            circuit
+                .export(*target)
+                .control_by(&mut circuit, control)
+                .unwrap()
+        });
+        (0..1 << n).for_each(|address: usize| {
+            let mut initial = vec![false; circuit.qubits.len()];
+            initial[datas[0].0] = address & 2 == 2;
+            initial[datas[1].0] = address & 1 == 1;
+            initial[first.0] = true;
+            let simulator = simulate(&circuit, initial.clone());
+            let (state, _) = simulator.single_state().unwrap();
+            let mut expected = initial;
+            expected[targets[address].0] = true;
+            assert_eq!(state, &expected);
+        });
+    }
+    #[test]
+    fn measurement_halves_t_count() {
+        let n = 3;
+        let mut circuit = Circuit::new();
+        let first = circuit.add_qubit("first").unwrap();
+        let first_control = circuit.control(first);
+        let datas = circuit.register("data", n as usize).unwrap();
+        let ancillas = circuit.register("ancilla", n as usize).unwrap();
+        in_over_2n_with(
+            &mut circuit,
+            n,
+            &first_control,
+            &datas,
+            &ancillas,
+            Uncompute::Measurement,
+        )
+        .unwrap();
+        // in_layer 1回につき計算のT 4個だけが残る
+        assert_eq!(circuit.stats().t_count, 4 * 7);
+        assert_eq!(circuit.clbits.len(), 7);
+        // circuit_statsと同じくqasmを読み直しても同じT数になる
+        let parsed = from_qasm(&circuit.to_qasm().to_string()).unwrap();
+        assert_eq!(parsed.stats().t_count, 4 * 7);
+    }
+    #[test]
+    fn rejects_mismatched_lengths() {
+        let mut circuit = Circuit::new();
+        let first = circuit.add_qubit("first").unwrap();
+        let first_control = circuit.control(first);
+        let datas = circuit.register("data", 2).unwrap();
+        let ancillas = circuit.register("ancilla", 1).unwrap();
+        assert_eq!(
+            in_over_2n(&mut circuit, 2, &first_control, &datas, &ancillas),
+            Err(Error::invalid_argument(
+                "datas and ancillas must be the same length"
+            ))
+        );
+        assert!(in_over_range(
+            &mut circuit,
+            1,
+            3,
+            &first_control,
+            &datas[..1],
+            &ancillas,
+            Uncompute::Toffoli
+        )
+        .is_err());
     }
 }
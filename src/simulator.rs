@@ -0,0 +1,5 @@
+pub mod basis;
+pub mod complex;
+pub mod equivalence;
+pub mod stabilizer;
+pub mod statevector;
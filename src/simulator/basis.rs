@@ -0,0 +1,412 @@
+// 計算基底の振幅だけを持つ疎なシミュレータ
+// X/CNOTは基底の置換、位相ゲートは振幅への掛け算として正確に扱う
+// Hは重ね合わせを作るが、Toffoliの分解の中では最後に打ち消し合うので状態は小さいまま保たれる
+use super::complex::Complex;
+use super::statevector::{rotation_matrix, Matrix};
+use crate::error::Error;
+use crate::primitive::{Circuit, ClbitId};
+use crate::qasm::operations::Operation;
+use crate::qasm::to_qasm::to_qasm;
+use crate::select_gates::layered::uniform_layered_internal_with_data;
+use crate::select_gates::simple_select_controls::Uncompute;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, VecDeque};
+use std::f64::consts::{FRAC_1_SQRT_2, FRAC_PI_4};
+use std::fmt;
+
+pub type BasisState = Vec<bool>;
+
+#[derive(Debug, Clone)]
+pub struct BasisSimulator {
+    pub qubit_count: usize,
+    pub amplitudes: HashMap<BasisState, Complex>,
+    // 測定結果。書き込まれていないbitは0
+    pub clbits: Vec<bool>,
+    // 測定結果は固定のseedで選ぶ
+    rng: StdRng,
+    // force_outcomesで指定された測定結果。空になったらrngで選ぶ
+    outcomes: VecDeque<bool>,
+}
+
+impl BasisSimulator {
+    pub fn new(initial: BasisState) -> Self {
+        let mut amplitudes = HashMap::new();
+        let qubit_count = initial.len();
+        amplitudes.insert(initial, Complex::ONE);
+        BasisSimulator {
+            qubit_count,
+            amplitudes,
+            clbits: Vec::new(),
+            rng: StdRng::seed_from_u64(0),
+            outcomes: VecDeque::new(),
+        }
+    }
+    // 以降の測定(resetを含む)の結果を順に指定する
+    // 確率0の結果を指定するとすべての振幅が消える
+    pub fn force_outcomes(&mut self, outcomes: &[bool]) {
+        self.outcomes.extend(outcomes);
+    }
+    fn permute(&mut self, f: impl Fn(&mut BasisState)) {
+        self.amplitudes = self
+            .amplitudes
+            .drain()
+            .map(|(mut state, amplitude)| {
+                f(&mut state);
+                (state, amplitude)
+            })
+            .collect();
+    }
+    fn phase(&mut self, target: usize, phase: Complex) {
+        self.amplitudes
+            .iter_mut()
+            .filter(|(state, _)| state[target])
+            .for_each(|(_, amplitude)| *amplitude = *amplitude * phase);
+    }
+    // 2x2の行列を掛ける。targetが0/1の基底の両方に振幅が分かれる
+    fn unitary(&mut self, target: usize, [[a, b], [c, d]]: Matrix) {
+        let mut amplitudes: HashMap<BasisState, Complex> = HashMap::new();
+        self.amplitudes.drain().for_each(|(state, amplitude)| {
+            let (to_zero, to_one) = if state[target] { (b, d) } else { (a, c) };
+            let mut zero = state.clone();
+            zero[target] = false;
+            let mut one = state;
+            one[target] = true;
+            *amplitudes.entry(zero).or_default() += to_zero * amplitude;
+            *amplitudes.entry(one).or_default() += to_one * amplitude;
+        });
+        // 打ち消し合った基底は消しておく
+        amplitudes.retain(|_, amplitude| !amplitude.is_zero());
+        self.amplitudes = amplitudes;
+    }
+    fn hadamard(&mut self, target: usize) {
+        let h = Complex::new(FRAC_1_SQRT_2, 0.0);
+        self.unitary(target, [[h, h], [h, -h]]);
+    }
+    // targetを測定して、結果の側へ射影する
+    fn measure(&mut self, target: usize) -> bool {
+        let one_probability = self
+            .amplitudes
+            .iter()
+            .filter(|(state, _)| state[target])
+            .map(|(_, amplitude)| amplitude.norm_sqr())
+            .sum::<f64>();
+        let outcome = match self.outcomes.pop_front() {
+            Some(outcome) => outcome,
+            None => self.rng.gen::<f64>() < one_probability,
+        };
+        let probability = if outcome {
+            one_probability
+        } else {
+            1.0 - one_probability
+        };
+        let factor = if probability > 0.0 {
+            1.0 / probability.sqrt()
+        } else {
+            0.0
+        };
+        self.amplitudes.retain(|state, _| state[target] == outcome);
+        self.amplitudes
+            .values_mut()
+            .for_each(|amplitude| *amplitude = amplitude.scale(factor));
+        outcome
+    }
+    pub fn clbit(&self, clbit: ClbitId) -> bool {
+        self.clbits.get(clbit.0).copied().unwrap_or(false)
+    }
+    pub fn apply(&mut self, operation: &Operation) {
+        match operation {
+            Operation::Z(target) => self.phase(target.0 as usize, -Complex::ONE),
+            Operation::H(target) => self.hadamard(target.0 as usize),
+            Operation::X(target) => {
+                let target = target.0 as usize;
+                self.permute(|state| state[target] = !state[target]);
+            }
+            Operation::T(target) => self.phase(target.0 as usize, Complex::from_phase(FRAC_PI_4)),
+            Operation::TDag(target) => {
+                self.phase(target.0 as usize, Complex::from_phase(-FRAC_PI_4))
+            }
+            Operation::S(target) => self.phase(target.0 as usize, Complex::I),
+            Operation::SDag(target) => self.phase(target.0 as usize, -Complex::I),
+            Operation::Rz(_, target)
+            | Operation::Rx(_, target)
+            | Operation::Ry(_, target)
+            | Operation::U(_, _, _, target) => {
+                self.unitary(target.0 as usize, rotation_matrix(operation).unwrap())
+            }
+            Operation::CX(from, to) => {
+                let (from, to) = (from.0 as usize, to.0 as usize);
+                self.permute(|state| {
+                    if state[from] {
+                        state[to] = !state[to];
+                    }
+                });
+            }
+            Operation::Measure(target, clbit) => {
+                let outcome = self.measure(target.0 as usize);
+                if self.clbits.len() <= clbit.0 {
+                    self.clbits.resize(clbit.0 + 1, false);
+                }
+                self.clbits[clbit.0] = outcome;
+            }
+            Operation::Reset(target) => {
+                if self.measure(target.0 as usize) {
+                    self.apply(&Operation::X(*target));
+                }
+            }
+            Operation::If(clbit, gate, target) => {
+                if self.clbit(*clbit) {
+                    self.apply(&Operation::gate(*gate, *target));
+                }
+            }
+        }
+    }
+    pub fn run(&mut self, operations: &[Operation]) {
+        operations
+            .iter()
+            .for_each(|operation| self.apply(operation));
+    }
+    // 1つの基底状態だけが残っている場合はそれを返す
+    pub fn single_state(&self) -> Option<(&BasisState, Complex)> {
+        let mut states = self.amplitudes.iter();
+        match (states.next(), states.next()) {
+            (Some((state, amplitude)), None) if (amplitude.norm() - 1.0).abs() < 1e-6 => {
+                Some((state, *amplitude))
+            }
+            _ => None,
+        }
+    }
+}
+
+// primitiveの回路をinitialから走らせる
+pub fn simulate(circuit: &Circuit, initial: BasisState) -> BasisSimulator {
+    simulate_with_outcomes(circuit, initial, &[])
+}
+
+// 測定結果をoutcomesの順に固定して走らせる
+pub fn simulate_with_outcomes(
+    circuit: &Circuit,
+    initial: BasisState,
+    outcomes: &[bool],
+) -> BasisSimulator {
+    let file = to_qasm(circuit);
+    let mut simulator = BasisSimulator::new(initial);
+    simulator.force_outcomes(outcomes);
+    simulator.run(&file.operations);
+    simulator
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QromError {
+    // テーブルが不正などで回路を作れなかった
+    Build(Error),
+    // 出力が重ね合わせになってしまった
+    NotBasisState {
+        address: usize,
+    },
+    Target {
+        address: usize,
+        expected: Vec<bool>,
+        actual: Vec<bool>,
+    },
+    // data, ancilla, firstのいずれかが元に戻っていない
+    NotRestored {
+        address: usize,
+    },
+    // addressごとに位相が違うと重ね合わせの入力で壊れる
+    Phase {
+        address: usize,
+    },
+}
+
+impl fmt::Display for QromError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QromError::Build(error) => write!(f, "{}", error),
+            QromError::NotBasisState { address } => {
+                write!(f, "address {} does not end in a basis state", address)
+            }
+            QromError::Target {
+                address,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "address {} wrote {:?} instead of {:?}",
+                address, actual, expected
+            ),
+            QromError::NotRestored { address } => {
+                write!(f, "address {} does not restore the control qubits", address)
+            }
+            QromError::Phase { address } => {
+                write!(f, "address {} picks up a relative phase", address)
+            }
+        }
+    }
+}
+
+impl std::error::Error for QromError {}
+
+// すべてのaddressについて、targetにdata[address]が書き込まれ、それ以外が元に戻ることを確かめる
+// addressはdata_0を最上位bitとして読む
+// dataがちょうど2^n行ならuniform_layeredと同じin_over_2n、短い場合は刈り取ったin_over_rangeを検証する
+pub fn verify_qrom(n: i32, data: &[Vec<bool>], uncompute: Uncompute) -> Result<(), QromError> {
+    let width = data.first().map(|row| row.len()).unwrap_or(0);
+    // 2^nがusizeに収まらない場合はどの長さのテーブルでも刈り取る
+    let rows = 1usize.checked_shl(n.max(0) as u32).unwrap_or(usize::MAX);
+    let length = if n > 0 && !data.is_empty() && data.len() < rows {
+        Some(data.len())
+    } else {
+        None
+    };
+    let circuit = uniform_layered_internal_with_data(n, length, width, data, uncompute)
+        .map_err(QromError::Build)?;
+    verify_qrom_circuit(&circuit, n, width, data, &[])
+}
+
+// uniform_layered_internal_with_dataで作ったcircuitを検証する
+// 測定結果はoutcomesの順に固定し、足りない分はrngで選ぶ
+fn verify_qrom_circuit(
+    circuit: &Circuit,
+    n: i32,
+    width: usize,
+    data: &[Vec<bool>],
+    outcomes: &[bool],
+) -> Result<(), QromError> {
+    let n = n as usize;
+    // data, ancilla, target, firstの順に並んでいる
+    let qubit_count = 2 * n + width + 1;
+    let mut reference_phase = None;
+    data.iter().enumerate().try_for_each(|(address, row)| {
+        let mut initial = vec![false; qubit_count];
+        (0..n).for_each(|bit| initial[bit] = (address >> (n - 1 - bit)) & 1 == 1);
+        initial[qubit_count - 1] = true;
+        let simulator = simulate_with_outcomes(circuit, initial.clone(), outcomes);
+        let (state, amplitude) = simulator
+            .single_state()
+            .ok_or(QromError::NotBasisState { address })?;
+        let actual = state[2 * n..2 * n + width].to_vec();
+        if &actual != row {
+            return Err(QromError::Target {
+                address,
+                expected: row.clone(),
+                actual,
+            });
+        }
+        let restored = state[..2 * n] == initial[..2 * n] && state[qubit_count - 1];
+        if !restored {
+            return Err(QromError::NotRestored { address });
+        }
+        match reference_phase {
+            None => reference_phase = Some(amplitude),
+            Some(phase) if !amplitude.approx_eq(phase) => return Err(QromError::Phase { address }),
+            Some(_) => {}
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gates::toffoli;
+    use crate::primitive::PrimitiveGate;
+    use crate::select_gates::data_combine::{generate_datas, rng_from_seed};
+    #[test]
+    fn toffoli_truth_table() {
+        (0..4).for_each(|input| {
+            let mut circuit = Circuit::new();
+            let q1 = circuit.add_qubit("q1").unwrap();
+            let q2 = circuit.add_qubit("q2").unwrap();
+            let q3 = circuit.add_qubit("q3").unwrap();
+            toffoli(&mut circuit, q1, q2, q3).unwrap();
+            let (a, b) = (input & 1 == 1, input & 2 == 2);
+            let simulator = simulate(&circuit, vec![a, b, false]);
+            let (state, amplitude) = simulator.single_state().unwrap();
+            assert_eq!(state, &vec![a, b, a && b]);
+            assert!(amplitude.approx_eq(Complex::ONE));
+        });
+    }
+    #[test]
+    fn qrom_writes_table() {
+        (1..=3).for_each(|n| {
+            let data = generate_datas(1 << n, 2, 0.5, &mut rng_from_seed(Some(n as u64)));
+            assert_eq!(verify_qrom(n, &data, Uncompute::Toffoli), Ok(()));
+        });
+    }
+    #[test]
+    fn measurement_uncompute_writes_table() {
+        // すべての測定について、0と1の両方の結果でテーブルが書き込まれることを確かめる
+        let check = |n: i32, length: Option<usize>, data: &[Vec<bool>]| {
+            let circuit =
+                uniform_layered_internal_with_data(n, length, 1, data, Uncompute::Measurement)
+                    .unwrap();
+            let measurements = circuit.clbits.len();
+            (0..1usize << measurements).for_each(|pattern| {
+                let outcomes = (0..measurements)
+                    .map(|bit| (pattern >> bit) & 1 == 1)
+                    .collect::<Vec<_>>();
+                assert_eq!(
+                    verify_qrom_circuit(&circuit, n, 1, data, &outcomes),
+                    Ok(()),
+                    "outcomes {:?}",
+                    outcomes
+                );
+            });
+        };
+        (1..=3).for_each(|n| {
+            let data = generate_datas(1 << n, 1, 0.5, &mut rng_from_seed(Some(n as u64)));
+            check(n, None, &data);
+        });
+        let data = generate_datas(5, 1, 0.5, &mut rng_from_seed(Some(5)));
+        check(3, Some(5), &data);
+        assert_eq!(verify_qrom(3, &data, Uncompute::Measurement), Ok(()));
+    }
+    #[test]
+    fn forced_outcome_is_recorded() {
+        // |+>を測定すると、指定した結果がclbitに書き込まれてその側へ射影される
+        let mut circuit = Circuit::new();
+        let q = circuit.add_qubit("q").unwrap();
+        let clbit = circuit.add_clbit("m");
+        circuit.gate(q, PrimitiveGate::H);
+        circuit.measure(q, clbit);
+        [false, true].iter().for_each(|&outcome| {
+            let simulator = simulate_with_outcomes(&circuit, vec![false], &[outcome]);
+            assert_eq!(simulator.clbit(clbit), outcome);
+            let (state, amplitude) = simulator.single_state().unwrap();
+            assert_eq!(state, &vec![outcome]);
+            assert!(amplitude.approx_eq(Complex::ONE));
+        });
+    }
+    #[test]
+    fn full_table_checks_both_paths() {
+        // 2^n行のテーブルはin_over_2nで作られるが、in_over_rangeで長さ2^nを指定しても同じ結果になる
+        (1..=3).for_each(|n| {
+            let data = generate_datas(1 << n, 2, 0.5, &mut rng_from_seed(Some(n as u64)));
+            [Uncompute::Toffoli, Uncompute::Measurement]
+                .iter()
+                .for_each(|&uncompute| {
+                    [None, Some(1 << n)].iter().for_each(|&length| {
+                        let circuit =
+                            uniform_layered_internal_with_data(n, length, 2, &data, uncompute)
+                                .unwrap();
+                        assert_eq!(verify_qrom_circuit(&circuit, n, 2, &data, &[]), Ok(()));
+                    });
+                });
+        });
+    }
+    #[test]
+    fn pruned_qrom_writes_table() {
+        (1..=8).for_each(|length| {
+            let data = generate_datas(length, 1, 0.5, &mut rng_from_seed(Some(length as u64)));
+            assert_eq!(verify_qrom(3, &data, Uncompute::Toffoli), Ok(()));
+        });
+    }
+    #[test]
+    fn qrom_rejects_bad_table() {
+        assert!(matches!(
+            verify_qrom(2, &vec![vec![true]; 5], Uncompute::Toffoli),
+            Err(QromError::Build(Error::DataTable(_)))
+        ));
+    }
+}
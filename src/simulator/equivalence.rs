@@ -0,0 +1,269 @@
+// 2つの回路が大域位相を除いて同じユニタリかどうかを調べる
+// 入力状態|ψ>ごとに<Bψ|Aψ>を計算し、すべて同じ絶対値1の位相になれば等しい
+use super::complex::Complex;
+use super::stabilizer::{clifford_equivalent, Tableau};
+use super::statevector::StateVector;
+use crate::error::Result;
+use crate::qasm::operations::File;
+use crate::select_gates::data_combine::rng_from_seed;
+use rand::Rng;
+
+// これ以下の幅では全ての基底状態を入力してユニタリ全体を比べる
+pub const UNITARY_MAX_QUBITS: usize = 10;
+// それより大きい幅でのランダムな入力状態の数
+pub const PROBES: usize = 4;
+
+// 各入力について<b|a>が共通の位相になっているかを追跡する
+struct PhaseCheck(Option<Complex>);
+
+impl PhaseCheck {
+    fn check(&mut self, a: &StateVector, b: &StateVector) -> bool {
+        let overlap = b.inner_product(a);
+        if (overlap.norm() - 1.0).abs() > 1e-6 {
+            return false;
+        }
+        match self.0 {
+            None => {
+                self.0 = Some(overlap);
+                true
+            }
+            Some(phase) => (overlap - phase).norm() < 1e-6,
+        }
+    }
+}
+
+fn run(file: &File, state: &StateVector) -> StateVector {
+    let mut state = state.clone();
+    state.run(&file.operations);
+    state
+}
+
+// 各成分が[-1, 1)の一様乱数の状態を正規化する
+fn random_state<R: Rng>(qubit_count: usize, rng: &mut R) -> Result<StateVector> {
+    let mut state = StateVector::new(qubit_count)?;
+    state.amplitudes.iter_mut().for_each(|amplitude| {
+        *amplitude = Complex::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0))
+    });
+    let norm = state
+        .amplitudes
+        .iter()
+        .map(|amplitude| amplitude.norm_sqr())
+        .sum::<f64>()
+        .sqrt();
+    state
+        .amplitudes
+        .iter_mut()
+        .for_each(|amplitude| *amplitude = amplitude.scale(1.0 / norm));
+    Ok(state)
+}
+
+// Cliffordだけの回路はtableauで幅に関係なく正確に比べる
+// 小さい幅では全ての基底状態を、大きい幅ではPROBES個のランダムな状態を入力する
+// ランダムな状態が両者の差(B†A)の固有ベクトルになる確率は0なので、1つでも十分に判定できる
+pub fn equivalent_with_seed(a: &File, b: &File, seed: Option<u64>) -> Result<bool> {
+    if a.qubit_count != b.qubit_count {
+        return Ok(false);
+    }
+    let is_clifford = |file: &File| file.operations.iter().all(Tableau::is_clifford);
+    if is_clifford(a) && is_clifford(b) {
+        return Ok(clifford_equivalent(a, b).unwrap_or(false));
+    }
+    let qubit_count = a.qubit_count;
+    let mut phase = PhaseCheck(None);
+    if qubit_count <= UNITARY_MAX_QUBITS {
+        for index in 0..1 << qubit_count {
+            let state = StateVector::from_basis(qubit_count, index)?;
+            if !phase.check(&run(a, &state), &run(b, &state)) {
+                return Ok(false);
+            }
+        }
+    } else {
+        let mut rng = rng_from_seed(seed);
+        for _ in 0..PROBES {
+            let state = random_state(qubit_count, &mut rng)?;
+            if !phase.check(&run(a, &state), &run(b, &state)) {
+                return Ok(false);
+            }
+        }
+    }
+    Ok(true)
+}
+
+pub fn equivalent(a: &File, b: &File) -> Result<bool> {
+    equivalent_with_seed(a, b, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gates::{mcx, toffoli_first_control, toffoli_first_control_dag, McxStrategy};
+    use crate::primitive::{self, Angle, Circuit, PrimitiveGate};
+    use crate::qasm::operations::{Operation, QubitId};
+    use crate::qasm::to_qasm::to_qasm;
+    use crate::select_gates::layered::uniform_layered_internal_with_data;
+    use crate::select_gates::simple_select_controls::{in_layer, Uncompute};
+
+    fn qubits(count: usize) -> (Circuit, Vec<primitive::QubitId>) {
+        let mut circuit = Circuit::new();
+        let qs = (0..count)
+            .map(|i| circuit.add_qubit(&format!("q_{}", i)).unwrap())
+            .collect();
+        (circuit, qs)
+    }
+    #[test]
+    fn toffoli_uncompute() {
+        let (mut circuit, qs) = qubits(3);
+        let control = circuit.control(qs[0]);
+        toffoli_first_control(&mut circuit, &control, qs[1], qs[2]).unwrap();
+        let compute = to_qasm(&circuit);
+        toffoli_first_control_dag(&mut circuit, &control, qs[1], qs[2]).unwrap();
+        let round_trip = to_qasm(&circuit);
+        let identity = File {
+            qubit_count: 3,
+            clbit_count: 0,
+            operations: vec![],
+        };
+        assert_eq!(equivalent(&round_trip, &identity), Ok(true));
+        assert_eq!(equivalent(&compute, &identity), Ok(false));
+    }
+    #[test]
+    fn t_squared_is_s() {
+        let q0 = QubitId(0);
+        let tt = File {
+            qubit_count: 1,
+            clbit_count: 0,
+            operations: vec![Operation::T(q0), Operation::T(q0)],
+        };
+        let s = File {
+            qubit_count: 1,
+            clbit_count: 0,
+            operations: vec![Operation::S(q0)],
+        };
+        let sdag = File {
+            qubit_count: 1,
+            clbit_count: 0,
+            operations: vec![Operation::SDag(q0)],
+        };
+        assert_eq!(equivalent(&tt, &s), Ok(true));
+        assert_eq!(equivalent(&tt, &sdag), Ok(false));
+    }
+    #[test]
+    fn rotations() {
+        let single = |operation: Operation| File {
+            qubit_count: 1,
+            clbit_count: 0,
+            operations: vec![operation],
+        };
+        let q0 = QubitId(0);
+        let quarter = Angle::pi_ratio(1, 4).unwrap();
+        let half = Angle::pi_ratio(1, 2).unwrap();
+        // 大域位相を除いてRz(π/4) = T, Rz(π/2) = S, U(π/2, 0, π) = H
+        assert_eq!(
+            equivalent(
+                &single(Operation::Rz(quarter, q0)),
+                &single(Operation::T(q0))
+            ),
+            Ok(true)
+        );
+        assert_eq!(
+            equivalent(&single(Operation::Rz(half, q0)), &single(Operation::S(q0))),
+            Ok(true)
+        );
+        let u = Operation::U(
+            half,
+            Angle::pi_ratio(0, 1).unwrap(),
+            Angle::pi_ratio(1, 1).unwrap(),
+            q0,
+        );
+        assert_eq!(equivalent(&single(u), &single(Operation::H(q0))), Ok(true));
+        assert_eq!(
+            equivalent(&single(Operation::Ry(half, q0)), &single(Operation::H(q0))),
+            Ok(false)
+        );
+    }
+    // circuitの後にinverseを続けたfile
+    fn with_inverse(circuit: &Circuit) -> File {
+        let mut file = to_qasm(circuit);
+        file.operations
+            .extend(to_qasm(&circuit.inverse().unwrap()).operations);
+        file
+    }
+    #[test]
+    fn inverse_is_identity() {
+        let (mut circuit, qs) = qubits(5);
+        circuit.gate(qs[0], PrimitiveGate::H);
+        circuit.gate(qs[1], PrimitiveGate::T);
+        circuit.gate(qs[2], PrimitiveGate::Rx(Angle::pi_ratio(1, 3).unwrap()));
+        circuit.gate(
+            qs[3],
+            PrimitiveGate::U(
+                Angle::pi_ratio(1, 2).unwrap(),
+                Angle::pi_ratio(1, 4).unwrap(),
+                Angle::Radians(0.3),
+            ),
+        );
+        let control = circuit.control(qs[0]);
+        let (left, right) = in_layer(&mut circuit, &control, qs[1], qs[2]).unwrap();
+        circuit
+            .export(qs[3])
+            .control_by(&mut circuit, &left)
+            .unwrap();
+        circuit
+            .export(qs[4])
+            .control_by(&mut circuit, &right)
+            .unwrap();
+        mcx(
+            &mut circuit,
+            &qs[..3],
+            qs[4],
+            &qs[3..4],
+            McxStrategy::DirtyVChain,
+        )
+        .unwrap();
+        let identity = File {
+            qubit_count: 5,
+            clbit_count: 0,
+            operations: vec![],
+        };
+        assert_eq!(equivalent(&with_inverse(&circuit), &identity), Ok(true));
+        assert_eq!(equivalent(&to_qasm(&circuit), &identity), Ok(false));
+    }
+    #[test]
+    fn inverse_of_qrom() {
+        let table = vec![vec![true, false], vec![false, true], vec![true, true]];
+        let circuit =
+            uniform_layered_internal_with_data(2, Some(3), 2, &table, Uncompute::Toffoli).unwrap();
+        let identity = File {
+            qubit_count: circuit.qubits.len(),
+            clbit_count: 0,
+            operations: vec![],
+        };
+        assert_eq!(equivalent(&with_inverse(&circuit), &identity), Ok(true));
+    }
+    #[test]
+    fn wide_probing() {
+        let qubit_count = UNITARY_MAX_QUBITS + 2;
+        let last = QubitId(qubit_count as i32 - 1);
+        let fan_out = (1..qubit_count)
+            .map(|i| Operation::CX(QubitId(0), QubitId(i as i32)))
+            .collect::<Vec<_>>();
+        let mut before = vec![Operation::H(QubitId(0)), Operation::T(last)];
+        before.extend(fan_out.iter().copied());
+        let mut after = vec![Operation::H(QubitId(0))];
+        after.extend(fan_out.iter().copied());
+        after.push(Operation::T(last));
+        let a = File {
+            qubit_count,
+            clbit_count: 0,
+            operations: before,
+        };
+        // lastはCNOTのtargetなので、TをCNOTの後ろに動かすと別の回路になる
+        let b = File {
+            qubit_count,
+            clbit_count: 0,
+            operations: after,
+        };
+        assert_eq!(equivalent_with_seed(&a, &a.clone(), Some(1)), Ok(true));
+        assert_eq!(equivalent_with_seed(&a, &b, Some(1)), Ok(false));
+    }
+}
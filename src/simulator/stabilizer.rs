@@ -0,0 +1,321 @@
+// Aaronson-GottesmanのCHP形式のtableauシミュレータ
+// Clifford(Z, H, X, S, SDag, CX)だけを扱い、T/TDagはエラーにする
+// 行0..nはdestabilizer、行n..2nはstabilizer、行2nは測定用の作業領域
+use crate::qasm::operations::{File, Operation};
+use rand::Rng;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StabilizerError {
+    NonClifford { index: usize, operation: Operation },
+}
+
+impl fmt::Display for StabilizerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StabilizerError::NonClifford { index, operation } => write!(
+                f,
+                "operation {} ({}) is not a Clifford gate",
+                index,
+                operation.to_string()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StabilizerError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tableau {
+    pub qubit_count: usize,
+    x: Vec<Vec<bool>>,
+    z: Vec<Vec<bool>>,
+    r: Vec<bool>,
+}
+
+impl Tableau {
+    // |0...0>の状態(恒等写像)から始める
+    pub fn new(qubit_count: usize) -> Self {
+        let rows = 2 * qubit_count + 1;
+        let mut x = vec![vec![false; qubit_count]; rows];
+        let mut z = vec![vec![false; qubit_count]; rows];
+        (0..qubit_count).for_each(|i| {
+            x[i][i] = true;
+            z[qubit_count + i][i] = true;
+        });
+        Tableau {
+            qubit_count,
+            x,
+            z,
+            r: vec![false; rows],
+        }
+    }
+    fn rows(&self) -> std::ops::Range<usize> {
+        0..2 * self.qubit_count
+    }
+    fn hadamard(&mut self, a: usize) {
+        for i in self.rows() {
+            self.r[i] ^= self.x[i][a] && self.z[i][a];
+            std::mem::swap(&mut self.x[i][a], &mut self.z[i][a]);
+        }
+    }
+    fn phase(&mut self, a: usize) {
+        for i in self.rows() {
+            self.r[i] ^= self.x[i][a] && self.z[i][a];
+            self.z[i][a] ^= self.x[i][a];
+        }
+    }
+    // XはZを、ZはXを反転させる
+    fn pauli(&mut self, a: usize, x: bool, z: bool) {
+        for i in self.rows() {
+            self.r[i] ^= (x && self.z[i][a]) ^ (z && self.x[i][a]);
+        }
+    }
+    fn cnot(&mut self, a: usize, b: usize) {
+        for i in self.rows() {
+            self.r[i] ^= self.x[i][a] && self.z[i][b] && (self.x[i][b] == self.z[i][a]);
+            self.x[i][b] ^= self.x[i][a];
+            self.z[i][a] ^= self.z[i][b];
+        }
+    }
+    // S^turns
+    fn phases(&mut self, a: usize, turns: i64) {
+        (0..turns.rem_euclid(4)).for_each(|_| self.phase(a));
+    }
+    // 回転ゲートはπ/2の整数倍のRz/RxだけがClifford
+    pub fn is_clifford(operation: &Operation) -> bool {
+        match operation {
+            Operation::T(_)
+            | Operation::TDag(_)
+            | Operation::Ry(_, _)
+            | Operation::U(_, _, _, _) => false,
+            // 測定結果で分岐するものは扱わない
+            Operation::Measure(_, _) | Operation::Reset(_) | Operation::If(_, _, _) => false,
+            Operation::Rz(angle, _) | Operation::Rx(angle, _) => angle.quarter_turns().is_some(),
+            _ => true,
+        }
+    }
+    // Clifford以外の場合は何もせずにfalseを返す
+    pub fn apply(&mut self, operation: &Operation) -> bool {
+        match operation {
+            Operation::Z(target) => self.pauli(target.0 as usize, false, true),
+            Operation::H(target) => self.hadamard(target.0 as usize),
+            Operation::X(target) => self.pauli(target.0 as usize, true, false),
+            Operation::S(target) => self.phase(target.0 as usize),
+            Operation::SDag(target) => {
+                self.phase(target.0 as usize);
+                self.pauli(target.0 as usize, false, true);
+            }
+            Operation::CX(from, to) => self.cnot(from.0 as usize, to.0 as usize),
+            // Rz(kπ/2)はS^kと大域位相だけ異なる
+            Operation::Rz(angle, target) => match angle.quarter_turns() {
+                Some(turns) => self.phases(target.0 as usize, turns),
+                None => return false,
+            },
+            Operation::Rx(angle, target) => match angle.quarter_turns() {
+                Some(turns) => {
+                    let target = target.0 as usize;
+                    self.hadamard(target);
+                    self.phases(target, turns);
+                    self.hadamard(target);
+                }
+                None => return false,
+            },
+            Operation::T(_)
+            | Operation::TDag(_)
+            | Operation::Ry(_, _)
+            | Operation::U(_, _, _, _)
+            | Operation::Measure(_, _)
+            | Operation::Reset(_)
+            | Operation::If(_, _, _) => return false,
+        }
+        true
+    }
+    pub fn run(&mut self, operations: &[Operation]) -> Result<(), StabilizerError> {
+        operations
+            .iter()
+            .enumerate()
+            .try_for_each(|(index, operation)| {
+                if self.apply(operation) {
+                    Ok(())
+                } else {
+                    Err(StabilizerError::NonClifford {
+                        index,
+                        operation: *operation,
+                    })
+                }
+            })
+    }
+    // 行iと行hの積を行hに入れる(位相も追跡する)
+    fn rowsum(&mut self, h: usize, i: usize) {
+        // x1z1とx2z2の積で出てくるiの冪
+        fn g(x1: bool, z1: bool, x2: bool, z2: bool) -> i32 {
+            match (x1, z1) {
+                (false, false) => 0,
+                (true, true) => z2 as i32 - x2 as i32,
+                (true, false) => z2 as i32 * (2 * x2 as i32 - 1),
+                (false, true) => x2 as i32 * (1 - 2 * z2 as i32),
+            }
+        }
+        let sum = 2 * (self.r[h] as i32)
+            + 2 * (self.r[i] as i32)
+            + (0..self.qubit_count)
+                .map(|j| g(self.x[i][j], self.z[i][j], self.x[h][j], self.z[h][j]))
+                .sum::<i32>();
+        self.r[h] = sum.rem_euclid(4) == 2;
+        for j in 0..self.qubit_count {
+            self.x[h][j] ^= self.x[i][j];
+            self.z[h][j] ^= self.z[i][j];
+        }
+    }
+    // Z基底で測定する。結果がランダムな場合はrngで決める
+    pub fn measure<R: Rng>(&mut self, a: usize, rng: &mut R) -> bool {
+        let n = self.qubit_count;
+        match (n..2 * n).find(|p| self.x[*p][a]) {
+            Some(p) => {
+                for i in 0..2 * n {
+                    if i != p && self.x[i][a] {
+                        self.rowsum(i, p);
+                    }
+                }
+                self.x[p - n] = self.x[p].clone();
+                self.z[p - n] = self.z[p].clone();
+                self.r[p - n] = self.r[p];
+                self.x[p] = vec![false; n];
+                self.z[p] = vec![false; n];
+                self.z[p][a] = true;
+                self.r[p] = rng.gen::<bool>();
+                self.r[p]
+            }
+            None => {
+                let scratch = 2 * n;
+                self.x[scratch] = vec![false; n];
+                self.z[scratch] = vec![false; n];
+                self.r[scratch] = false;
+                for i in 0..n {
+                    if self.x[i][a] {
+                        self.rowsum(scratch, i + n);
+                    }
+                }
+                self.r[scratch]
+            }
+        }
+    }
+    // 測定結果が決まっているかどうか
+    pub fn is_deterministic(&self, a: usize) -> bool {
+        let n = self.qubit_count;
+        !(n..2 * n).any(|p| self.x[p][a])
+    }
+}
+
+// fileのClifford写像をtableauとして求める
+pub fn tableau_of(file: &File) -> Result<Tableau, StabilizerError> {
+    let mut tableau = Tableau::new(file.qubit_count);
+    tableau.run(&file.operations)?;
+    Ok(tableau)
+}
+
+// 2つのClifford回路が大域位相を除いて等しいかどうか
+// 恒等写像から始めたtableauは各X_i, Z_iの像を符号付きで持つので、一致すればユニタリも一致する
+pub fn clifford_equivalent(a: &File, b: &File) -> Result<bool, StabilizerError> {
+    if a.qubit_count != b.qubit_count {
+        return Ok(false);
+    }
+    Ok(tableau_of(a)? == tableau_of(b)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qasm::operations::QubitId;
+    use crate::select_gates::data_combine::rng_from_seed;
+    fn file(qubit_count: usize, operations: Vec<Operation>) -> File {
+        File {
+            qubit_count,
+            clbit_count: 0,
+            operations,
+        }
+    }
+    #[test]
+    fn wide_fan_out() {
+        let n = 128;
+        let mut operations = vec![Operation::X(QubitId(0))];
+        operations.extend((1..n).map(|i| Operation::CX(QubitId(0), QubitId(i as i32))));
+        let mut tableau = tableau_of(&file(n, operations)).unwrap();
+        let mut rng = rng_from_seed(Some(0));
+        (0..n).for_each(|i| {
+            assert!(tableau.is_deterministic(i));
+            assert!(tableau.measure(i, &mut rng));
+        });
+    }
+    #[test]
+    fn ghz_is_correlated() {
+        let n = 100;
+        let mut operations = vec![Operation::H(QubitId(0))];
+        operations.extend((1..n).map(|i| Operation::CX(QubitId(i as i32 - 1), QubitId(i as i32))));
+        let mut tableau = tableau_of(&file(n, operations)).unwrap();
+        let mut rng = rng_from_seed(Some(3));
+        assert!(!tableau.is_deterministic(0));
+        let first = tableau.measure(0, &mut rng);
+        (1..n).for_each(|i| assert_eq!(tableau.measure(i, &mut rng), first));
+    }
+    #[test]
+    fn equivalences() {
+        let (q0, q1) = (QubitId(0), QubitId(1));
+        // HZH = X
+        assert_eq!(
+            clifford_equivalent(
+                &file(
+                    1,
+                    vec![Operation::H(q0), Operation::Z(q0), Operation::H(q0)]
+                ),
+                &file(1, vec![Operation::X(q0)])
+            ),
+            Ok(true)
+        );
+        // Hで挟むとCNOTの向きが逆になる
+        assert_eq!(
+            clifford_equivalent(
+                &file(
+                    2,
+                    vec![
+                        Operation::H(q0),
+                        Operation::H(q1),
+                        Operation::CX(q1, q0),
+                        Operation::H(q0),
+                        Operation::H(q1),
+                    ]
+                ),
+                &file(2, vec![Operation::CX(q0, q1)])
+            ),
+            Ok(true)
+        );
+        // SとSDagは異なる
+        assert_eq!(
+            clifford_equivalent(
+                &file(1, vec![Operation::S(q0)]),
+                &file(1, vec![Operation::SDag(q0)])
+            ),
+            Ok(false)
+        );
+        // XZとZXは大域位相-1だけ異なる
+        assert_eq!(
+            clifford_equivalent(
+                &file(1, vec![Operation::X(q0), Operation::Z(q0)]),
+                &file(1, vec![Operation::Z(q0), Operation::X(q0)])
+            ),
+            Ok(true)
+        );
+    }
+    #[test]
+    fn rejects_t() {
+        let q0 = QubitId(0);
+        assert_eq!(
+            tableau_of(&file(1, vec![Operation::H(q0), Operation::T(q0)])),
+            Err(StabilizerError::NonClifford {
+                index: 1,
+                operation: Operation::T(q0)
+            })
+        );
+    }
+}
@@ -0,0 +1,570 @@
+// 小さい回路向けの密な状態ベクトルシミュレータ
+// qubit qはindexのq bit目に対応する(qiskitと同じlittle endian)
+use super::complex::Complex;
+use crate::error::{Error, Result};
+use crate::primitive::{Angle, ClbitId};
+use crate::qasm::operations::{File, Operation};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+use std::f64::consts::{FRAC_1_SQRT_2, FRAC_PI_4};
+use std::fmt;
+
+// 1 qubitのゲートの行列 [[<0|U|0>, <0|U|1>], [<1|U|0>, <1|U|1>]]
+pub type Matrix = [[Complex; 2]; 2];
+
+// 回転ゲートの行列。それ以外はNone
+pub fn rotation_matrix(operation: &Operation) -> Option<Matrix> {
+    // (cos(θ/2), sin(θ/2))
+    let half = |angle: &Angle| {
+        let half = angle.radians() / 2.0;
+        (half.cos(), half.sin())
+    };
+    match operation {
+        Operation::Rz(angle, _) => {
+            let phase = angle.radians() / 2.0;
+            Some([
+                [Complex::from_phase(-phase), Complex::ZERO],
+                [Complex::ZERO, Complex::from_phase(phase)],
+            ])
+        }
+        Operation::Rx(angle, _) => {
+            let (c, s) = half(angle);
+            Some([
+                [Complex::new(c, 0.0), Complex::new(0.0, -s)],
+                [Complex::new(0.0, -s), Complex::new(c, 0.0)],
+            ])
+        }
+        Operation::Ry(angle, _) => {
+            let (c, s) = half(angle);
+            Some([
+                [Complex::new(c, 0.0), Complex::new(-s, 0.0)],
+                [Complex::new(s, 0.0), Complex::new(c, 0.0)],
+            ])
+        }
+        Operation::U(theta, phi, lambda, _) => {
+            let (c, s) = half(theta);
+            let (phi, lambda) = (phi.radians(), lambda.radians());
+            Some([
+                [Complex::new(c, 0.0), -Complex::from_phase(lambda).scale(s)],
+                [
+                    Complex::from_phase(phi).scale(s),
+                    Complex::from_phase(phi + lambda).scale(c),
+                ],
+            ])
+        }
+        _ => None,
+    }
+}
+
+// 2^24個の振幅で256MiB程度になる
+pub const MAX_QUBITS: usize = 24;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateVectorError {
+    TooManyQubits { qubit_count: usize },
+}
+
+impl fmt::Display for StateVectorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateVectorError::TooManyQubits { qubit_count } => write!(
+                f,
+                "{} qubits exceed the state vector limit of {}",
+                qubit_count, MAX_QUBITS
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StateVectorError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateVector {
+    pub qubit_count: usize,
+    pub amplitudes: Vec<Complex>,
+    // 測定結果。書き込まれていないbitは0
+    pub clbits: Vec<bool>,
+    // 測定結果は固定のseedで選ぶので、同じ回路は同じ結果になる
+    rng: StdRng,
+    // force_outcomesで指定された測定結果。空になったらrngで選ぶ
+    outcomes: VecDeque<bool>,
+}
+
+impl StateVector {
+    // |index>から始める
+    pub fn from_basis(qubit_count: usize, index: usize) -> Result<Self> {
+        if qubit_count > MAX_QUBITS {
+            return Err(StateVectorError::TooManyQubits { qubit_count }.into());
+        }
+        if index >> qubit_count != 0 {
+            return Err(Error::invalid_argument(format!(
+                "basis index {} is out of range for {} qubits",
+                index, qubit_count
+            )));
+        }
+        let mut amplitudes = vec![Complex::ZERO; 1 << qubit_count];
+        amplitudes[index] = Complex::ONE;
+        Ok(StateVector {
+            qubit_count,
+            amplitudes,
+            clbits: Vec::new(),
+            rng: StdRng::seed_from_u64(0),
+            outcomes: VecDeque::new(),
+        })
+    }
+    pub fn new(qubit_count: usize) -> Result<Self> {
+        Self::from_basis(qubit_count, 0)
+    }
+    // 以降の測定(resetを含む)の結果を順に指定する
+    // 確率0の結果を指定するとすべての振幅が0になる
+    pub fn force_outcomes(&mut self, outcomes: &[bool]) {
+        self.outcomes.extend(outcomes);
+    }
+    fn phase(&mut self, target: usize, phase: Complex) {
+        let mask = 1 << target;
+        self.amplitudes
+            .iter_mut()
+            .enumerate()
+            .filter(|(index, _)| index & mask != 0)
+            .for_each(|(_, amplitude)| *amplitude = *amplitude * phase);
+    }
+    // target bitが0のindexごとに、(0, 1)の組へ2x2の操作をする
+    fn pairs(&mut self, target: usize, f: impl Fn(Complex, Complex) -> (Complex, Complex)) {
+        let mask = 1 << target;
+        (0..self.amplitudes.len())
+            .filter(|index| index & mask == 0)
+            .for_each(|index| {
+                let (zero, one) = f(self.amplitudes[index], self.amplitudes[index | mask]);
+                self.amplitudes[index] = zero;
+                self.amplitudes[index | mask] = one;
+            });
+    }
+    // targetを測定して、結果の側へ射影する
+    fn measure(&mut self, target: usize) -> bool {
+        let mask = 1 << target;
+        let one_probability = self
+            .amplitudes
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| index & mask != 0)
+            .map(|(_, amplitude)| amplitude.norm_sqr())
+            .sum::<f64>();
+        let outcome = match self.outcomes.pop_front() {
+            Some(outcome) => outcome,
+            None => self.rng.gen::<f64>() < one_probability,
+        };
+        let probability = if outcome {
+            one_probability
+        } else {
+            1.0 - one_probability
+        };
+        let factor = if probability > 0.0 {
+            1.0 / probability.sqrt()
+        } else {
+            0.0
+        };
+        self.amplitudes
+            .iter_mut()
+            .enumerate()
+            .for_each(|(index, amplitude)| {
+                *amplitude = if (index & mask != 0) == outcome {
+                    amplitude.scale(factor)
+                } else {
+                    Complex::ZERO
+                }
+            });
+        outcome
+    }
+    pub fn clbit(&self, clbit: ClbitId) -> bool {
+        self.clbits.get(clbit.0).copied().unwrap_or(false)
+    }
+    pub fn apply(&mut self, operation: &Operation) {
+        match operation {
+            Operation::Z(target) => self.phase(target.0 as usize, -Complex::ONE),
+            Operation::H(target) => self.pairs(target.0 as usize, |zero, one| {
+                (
+                    (zero + one).scale(FRAC_1_SQRT_2),
+                    (zero - one).scale(FRAC_1_SQRT_2),
+                )
+            }),
+            Operation::X(target) => self.pairs(target.0 as usize, |zero, one| (one, zero)),
+            Operation::T(target) => self.phase(target.0 as usize, Complex::from_phase(FRAC_PI_4)),
+            Operation::TDag(target) => {
+                self.phase(target.0 as usize, Complex::from_phase(-FRAC_PI_4))
+            }
+            Operation::S(target) => self.phase(target.0 as usize, Complex::I),
+            Operation::SDag(target) => self.phase(target.0 as usize, -Complex::I),
+            Operation::Rz(_, target)
+            | Operation::Rx(_, target)
+            | Operation::Ry(_, target)
+            | Operation::U(_, _, _, target) => {
+                let [[a, b], [c, d]] = rotation_matrix(operation).unwrap();
+                self.pairs(target.0 as usize, |zero, one| {
+                    (a * zero + b * one, c * zero + d * one)
+                })
+            }
+            Operation::CX(from, to) => {
+                let (from, to) = (1 << from.0, 1 << to.0);
+                (0..self.amplitudes.len())
+                    .filter(|index| index & from != 0 && index & to == 0)
+                    .for_each(|index| self.amplitudes.swap(index, index | to));
+            }
+            Operation::Measure(target, clbit) => {
+                let outcome = self.measure(target.0 as usize);
+                if self.clbits.len() <= clbit.0 {
+                    self.clbits.resize(clbit.0 + 1, false);
+                }
+                self.clbits[clbit.0] = outcome;
+            }
+            Operation::Reset(target) => {
+                if self.measure(target.0 as usize) {
+                    self.apply(&Operation::X(*target));
+                }
+            }
+            Operation::If(clbit, gate, target) => {
+                if self.clbit(*clbit) {
+                    self.apply(&Operation::gate(*gate, *target));
+                }
+            }
+        }
+    }
+    pub fn run(&mut self, operations: &[Operation]) {
+        operations
+            .iter()
+            .for_each(|operation| self.apply(operation));
+    }
+    // <self|other>
+    pub fn inner_product(&self, other: &StateVector) -> Complex {
+        self.amplitudes
+            .iter()
+            .zip(other.amplitudes.iter())
+            .fold(Complex::ZERO, |sum, (a, b)| sum + a.conj() * *b)
+    }
+    // |<self|other>| = 1なら大域位相を除いて等しい
+    pub fn equals_up_to_global_phase(&self, other: &StateVector) -> bool {
+        self.qubit_count == other.qubit_count
+            && (self.inner_product(other).norm() - 1.0).abs() < 1e-6
+    }
+}
+
+// |0...0>からfileを走らせる
+pub fn simulate_file(file: &File) -> Result<StateVector> {
+    simulate_file_from(file, 0)
+}
+
+// |index>からfileを走らせる
+pub fn simulate_file_from(file: &File, index: usize) -> Result<StateVector> {
+    let mut state = StateVector::from_basis(file.qubit_count, index)?;
+    state.run(&file.operations);
+    Ok(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gates::{
+        ccx, cnot, logical_and, logical_and_first_control, logical_and_uncompute,
+        logical_and_uncompute_first_control, mcx, toffoli, toffoli_first_control, McxStrategy,
+    };
+    use crate::primitive::{self, Circuit, PrimitiveGate};
+    use crate::qasm::operations::QubitId;
+    use crate::qasm::to_qasm::to_qasm;
+    use crate::select_gates::simple_select_controls::{in_layer, in_layer_with, Uncompute};
+
+    fn qubits(count: usize) -> (Circuit, Vec<primitive::QubitId>) {
+        let mut circuit = Circuit::new();
+        let qs = (0..count)
+            .map(|i| circuit.add_qubit(&format!("q_{}", i)).unwrap())
+            .collect();
+        (circuit, qs)
+    }
+    // 各入力indexについて期待するindexへ、共通の大域位相で移ることを確かめる
+    fn assert_maps(file: &File, cases: &[(usize, usize)]) {
+        let mut global_phase = None;
+        cases.iter().for_each(|(input, output)| {
+            let state = simulate_file_from(file, *input).unwrap();
+            let amplitude = state.amplitudes[*output];
+            assert!(
+                (amplitude.norm() - 1.0).abs() < 1e-6,
+                "{} -> {}",
+                input,
+                output
+            );
+            match global_phase {
+                None => global_phase = Some(amplitude),
+                Some(phase) => assert!(amplitude.approx_eq(phase), "{} -> {}", input, output),
+            }
+        });
+    }
+
+    #[test]
+    fn bell_state() {
+        let file = File {
+            qubit_count: 2,
+            clbit_count: 0,
+            operations: vec![
+                Operation::H(QubitId(0)),
+                Operation::CX(QubitId(0), QubitId(1)),
+            ],
+        };
+        let state = simulate_file(&file).unwrap();
+        assert!(state.amplitudes[0].approx_eq(Complex::new(FRAC_1_SQRT_2, 0.0)));
+        assert!(state.amplitudes[3].approx_eq(Complex::new(FRAC_1_SQRT_2, 0.0)));
+        assert!(state.amplitudes[1].is_zero() && state.amplitudes[2].is_zero());
+    }
+    #[test]
+    fn toffoli_is_logical_and() {
+        let (mut circuit, qs) = qubits(3);
+        toffoli(&mut circuit, qs[0], qs[1], qs[2]).unwrap();
+        let file = to_qasm(&circuit);
+        assert_maps(
+            &file,
+            &[
+                (0b000, 0b000),
+                (0b001, 0b001),
+                (0b010, 0b010),
+                (0b011, 0b111),
+            ],
+        );
+    }
+    #[test]
+    fn toffoli_first_control_is_logical_and() {
+        let (mut circuit, qs) = qubits(3);
+        let control = circuit.control(qs[0]);
+        toffoli_first_control(&mut circuit, &control, qs[1], qs[2]).unwrap();
+        let file = to_qasm(&circuit);
+        assert_maps(
+            &file,
+            &[
+                (0b000, 0b000),
+                (0b001, 0b001),
+                (0b010, 0b010),
+                (0b011, 0b111),
+            ],
+        );
+    }
+    #[test]
+    fn logical_and_is_logical_and() {
+        let (mut circuit, qs) = qubits(3);
+        logical_and(&mut circuit, qs[0], qs[1], qs[2]).unwrap();
+        let file = to_qasm(&circuit);
+        assert_maps(
+            &file,
+            &[
+                (0b000, 0b000),
+                (0b001, 0b001),
+                (0b010, 0b010),
+                (0b011, 0b111),
+            ],
+        );
+        let stats = circuit.stats();
+        assert_eq!((stats.t_count, stats.t_depth), (4, 2));
+    }
+    #[test]
+    fn logical_and_uncompute_restores_superposition() {
+        // 測定結果が0の場合と、CZで位相を直す1の場合の両方で元の状態に戻ることを確かめる
+        let prepare = |circuit: &mut Circuit, qs: &[primitive::QubitId]| {
+            circuit.gate(qs[0], PrimitiveGate::H);
+            circuit.gate(qs[1], PrimitiveGate::H);
+            circuit.gate(qs[1], PrimitiveGate::T);
+        };
+        let (mut expected, qs) = qubits(3);
+        prepare(&mut expected, &qs);
+        let expected = simulate_file(&to_qasm(&expected)).unwrap();
+        let run = |first_control: bool, outcome: bool| {
+            let (mut circuit, qs) = qubits(3);
+            prepare(&mut circuit, &qs);
+            if first_control {
+                let control = circuit.control(qs[0]);
+                logical_and_first_control(&mut circuit, &control, qs[1], qs[2]).unwrap();
+                logical_and_uncompute_first_control(&mut circuit, &control, qs[1], qs[2]).unwrap();
+            } else {
+                logical_and(&mut circuit, qs[0], qs[1], qs[2]).unwrap();
+                logical_and_uncompute(&mut circuit, qs[0], qs[1], qs[2]).unwrap();
+            }
+            let file = to_qasm(&circuit);
+            let mut state = StateVector::new(file.qubit_count).unwrap();
+            state.force_outcomes(&[outcome]);
+            state.run(&file.operations);
+            state
+        };
+        [false, true].iter().for_each(|&first_control| {
+            [false, true].iter().for_each(|&outcome| {
+                let actual = run(first_control, outcome);
+                assert_eq!(actual.clbits, vec![outcome]);
+                assert!(
+                    expected.equals_up_to_global_phase(&actual),
+                    "first_control: {}, outcome: {}",
+                    first_control,
+                    outcome
+                );
+            });
+        });
+    }
+    #[test]
+    fn ccx_is_exact() {
+        let (mut circuit, qs) = qubits(3);
+        ccx(&mut circuit, qs[0], qs[1], qs[2]).unwrap();
+        let file = to_qasm(&circuit);
+        let cases = (0..8)
+            .map(|input| {
+                (
+                    input,
+                    if input & 0b11 == 0b11 {
+                        input ^ 0b100
+                    } else {
+                        input
+                    },
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_maps(&file, &cases);
+    }
+    #[test]
+    fn mcx_strategies() {
+        // q_0..q_{k-1}: controls, q_k: target, 残り: ancilla
+        let strategies = [
+            McxStrategy::CleanVChain,
+            McxStrategy::DirtyVChain,
+            McxStrategy::NoAncilla,
+        ];
+        (0..=4).for_each(|k| {
+            strategies.iter().for_each(|&strategy| {
+                let ancilla_count = strategy.ancilla_count(k);
+                let (mut circuit, qs) = qubits(k + 1 + ancilla_count);
+                mcx(&mut circuit, &qs[..k], qs[k], &qs[k + 1..], strategy).unwrap();
+                let file = to_qasm(&circuit);
+                let all_controls = (1 << k) - 1;
+                // clean ancillaは|0>から始める
+                let ancilla_inputs = match strategy {
+                    McxStrategy::CleanVChain => 1,
+                    _ => 1 << ancilla_count,
+                };
+                let cases = (0..1 << (k + 1))
+                    .flat_map(|input| {
+                        (0..ancilla_inputs).map(move |ancilla| (ancilla << (k + 1)) | input)
+                    })
+                    .map(|input| {
+                        let flip = if input & all_controls == all_controls {
+                            1 << k
+                        } else {
+                            0
+                        };
+                        (input, input ^ flip)
+                    })
+                    .collect::<Vec<_>>();
+                assert_maps(&file, &cases);
+            });
+        });
+    }
+    #[test]
+    fn mcx_rejects_shared_qubits() {
+        let (mut circuit, qs) = qubits(5);
+        let strategy = McxStrategy::CleanVChain;
+        assert!(mcx(
+            &mut circuit,
+            &[qs[0], qs[0], qs[1]],
+            qs[2],
+            &[qs[3]],
+            strategy
+        )
+        .is_err());
+        assert!(mcx(&mut circuit, &qs[..3], qs[1], &[qs[3]], strategy).is_err());
+        assert!(mcx(&mut circuit, &qs[..3], qs[3], &[qs[2]], strategy).is_err());
+        assert!(mcx(&mut circuit, &qs[..3], qs[3], &[qs[4], qs[4]], strategy).is_err());
+        assert!(circuit.operations.is_empty());
+        assert!(mcx(&mut circuit, &qs[..3], qs[3], &[qs[4]], strategy).is_ok());
+    }
+    #[test]
+    fn in_layer_splits_control() {
+        // q0: control, q1: data, q2: ancilla, q3: 左の出力, q4: 右の出力
+        let (mut circuit, qs) = qubits(5);
+        let control = circuit.control(qs[0]);
+        let (left, right) = in_layer(&mut circuit, &control, qs[1], qs[2]).unwrap();
+        circuit
+            .export(qs[3])
+            .control_by(&mut circuit, &left)
+            .unwrap();
+        circuit
+            .export(qs[4])
+            .control_by(&mut circuit, &right)
+            .unwrap();
+        let file = to_qasm(&circuit);
+        assert_maps(
+            &file,
+            &[
+                (0b00000, 0b00000),
+                (0b00010, 0b00010),
+                (0b00001, 0b01001),
+                (0b00011, 0b10011),
+            ],
+        );
+    }
+    #[test]
+    fn measure_and_correct() {
+        // ベル状態の片方を測定して、結果に応じてもう片方を戻す
+        let (mut circuit, qs) = qubits(2);
+        let clbit = circuit.add_clbit("m");
+        circuit.gate(qs[0], PrimitiveGate::H);
+        cnot(&mut circuit, qs[0], qs[1]).unwrap();
+        circuit.measure(qs[0], clbit);
+        circuit.conditioned_gate(qs[1], clbit, PrimitiveGate::X);
+        circuit.reset(qs[0]);
+        let state = simulate_file(&to_qasm(&circuit)).unwrap();
+        assert!(state.amplitudes[0].approx_eq(Complex::ONE));
+    }
+    #[test]
+    fn in_layer_measurement_keeps_superposition() {
+        // controlとdataを重ね合わせにして、Toffoliで戻した場合と同じ状態になることを確かめる
+        // 測定結果が0の場合と、古典制御のCZで直す1の場合の両方を調べる
+        let run = |uncompute, outcomes: &[bool]| {
+            let (mut circuit, qs) = qubits(5);
+            circuit.gate(qs[0], PrimitiveGate::H);
+            circuit.gate(qs[1], PrimitiveGate::H);
+            let control = circuit.control(qs[0]);
+            let (left, right) =
+                in_layer_with(&mut circuit, &control, qs[1], qs[2], uncompute).unwrap();
+            circuit
+                .export(qs[3])
+                .control_by(&mut circuit, &left)
+                .unwrap();
+            circuit
+                .export(qs[4])
+                .control_by(&mut circuit, &right)
+                .unwrap();
+            let file = to_qasm(&circuit);
+            let mut state = StateVector::new(file.qubit_count).unwrap();
+            state.force_outcomes(outcomes);
+            state.run(&file.operations);
+            state
+        };
+        let expected = run(Uncompute::Toffoli, &[]);
+        [false, true].iter().for_each(|&outcome| {
+            let actual = run(Uncompute::Measurement, &[outcome]);
+            assert_eq!(actual.clbits, vec![outcome]);
+            assert!(expected.equals_up_to_global_phase(&actual), "{}", outcome);
+        });
+    }
+    #[test]
+    fn too_many_qubits() {
+        let file = File {
+            qubit_count: MAX_QUBITS + 1,
+            clbit_count: 0,
+            operations: vec![],
+        };
+        assert_eq!(
+            simulate_file(&file),
+            Err(Error::StateVector(StateVectorError::TooManyQubits {
+                qubit_count: MAX_QUBITS + 1
+            }))
+        );
+        assert_eq!(
+            StateVector::from_basis(2, 4),
+            Err(Error::invalid_argument(
+                "basis index 4 is out of range for 2 qubits"
+            ))
+        );
+        assert!(StateVector::from_basis(2, 3).is_ok());
+    }
+}